@@ -0,0 +1,203 @@
+//! The two bases either side of the core. They stand up to the weather better than anything
+//! the player builds, and while they're standing they put away extra from each harvest, light
+//! the homestead and give the player somewhere to fall back to. A hoe mends them during the day.
+//! For now the only thing that damages them is a solar flare, see `weather::flare_damage`.
+
+use bevy::prelude::*;
+
+use crate::{
+    day_night::{DayNightCycle, DayPhase},
+    game::Stockpile,
+    lighting::LightSource,
+    map::{self, MapSize},
+    player::{ClickEvent, Player, PlayerTool},
+    sfx::{Sfx, SfxEvent},
+    tools::Tools,
+};
+
+/// Where each base is centered, in homestead coordinates
+pub const BASE_TILES: [(i32, i32); 2] = [(13, 1), (27, 1)];
+/// Roof tiles on each base, they fall in as it takes damage
+pub const ROOF_PIECES: usize = 10;
+pub const BASE_MAX_HEALTH: f32 = 100.;
+/// Health one swing of the hoe puts back
+const REPAIR_AMOUNT: f32 = 10.;
+/// Resources each swing of repairs costs
+const REPAIR_COST: u32 = 1;
+/// Extra resources each standing base stores away from every harvest
+const BASE_STORAGE: u32 = 1;
+
+/// One of the bases
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Base {
+    /// Center tile on the map
+    pub center: (i32, i32),
+    pub health: f32,
+}
+
+impl Base {
+    pub fn intact(&self) -> bool {
+        self.health > 0.
+    }
+
+    pub fn damaged(&self) -> bool {
+        self.health < BASE_MAX_HEALTH
+    }
+
+    /// Whether a tile is inside the base's walls, where it gets hit and where the hoe mends it
+    pub fn contains(&self, (x, y): (i32, i32)) -> bool {
+        (x - self.center.0).abs() <= 3 && (y - self.center.1).abs() <= 1
+    }
+
+    /// Just outside the front of the base
+    pub fn door(&self) -> (i32, i32) {
+        (self.center.0, self.center.1 + 2)
+    }
+}
+
+/// Both bases, in the order of `BASE_TILES`
+#[derive(Resource, Clone, PartialEq, Debug)]
+pub struct Bases(pub Vec<Base>);
+
+impl Bases {
+    pub fn new(map_size: &MapSize) -> Bases {
+        Bases(BASE_TILES.iter().map(|(x, y)| Base { center: map_size.home(*x, *y), health: BASE_MAX_HEALTH }).collect())
+    }
+
+    /// The base a tile is part of
+    pub fn at(&self, tile: (i32, i32)) -> Option<usize> {
+        self.0.iter().position(|base| base.contains(tile))
+    }
+
+    /// Resources added on top of each harvest. The stockpile itself has no cap, so a base
+    /// falling only stops the bonus and never takes back what's already stored.
+    pub fn harvest_bonus(&self) -> u32 {
+        BASE_STORAGE * self.0.iter().filter(|base| base.intact()).count() as u32
+    }
+
+    /// Where the player goes back to, the first base still standing
+    pub fn respawn_point(&self) -> Option<(i32, i32)> {
+        self.0.iter().find(|base| base.intact()).map(|base| base.door())
+    }
+}
+
+impl Default for Bases {
+    fn default() -> Self {
+        Bases::new(&MapSize::default())
+    }
+}
+
+/// A roof tile on a base, shown while the base has enough health left
+#[derive(Component)]
+pub struct BaseRoof {
+    pub base: usize,
+    pub piece: usize,
+}
+
+/// A base's light, it goes out when the base falls
+#[derive(Component)]
+pub struct BaseLight(pub usize);
+
+/// Bases only get mended in daylight
+pub fn mending_hours(cycle: &DayNightCycle) -> bool {
+    matches!(cycle.phase, DayPhase::Dawn | DayPhase::Day)
+}
+
+/// Swinging the hoe at a damaged base during the day patches it up, for resources and a use of the hoe
+pub fn repair_bases(
+    mut ev_click: EventReader<ClickEvent>,
+    cycle: Res<DayNightCycle>,
+    mut bases: ResMut<Bases>,
+    mut stockpile: ResMut<Stockpile>,
+    mut tools: ResMut<Tools>,
+    mut ev_sfx: EventWriter<SfxEvent>,
+) {
+    for ClickEvent(tile, tool) in ev_click.read() {
+        if *tool != PlayerTool::Tiller || !mending_hours(&cycle) {
+            continue;
+        }
+        let Some(i) = bases.at(*tile) else { continue; };
+        if !bases.0[i].damaged() || tools.broken() || stockpile.0 < REPAIR_COST {
+            continue;
+        }
+        stockpile.0 -= REPAIR_COST;
+        tools.wear_hoe();
+        let base = &mut bases.0[i];
+        base.health = (base.health + REPAIR_AMOUNT).min(BASE_MAX_HEALTH);
+        ev_sfx.send(SfxEvent(Sfx::ToolUse));
+    }
+}
+
+/// Fallen bases go dark, and come back on once they're mended
+pub fn base_lights(bases: Res<Bases>, mut lights: Query<(&BaseLight, &mut LightSource)>) {
+    if !bases.is_changed() {
+        return;
+    }
+    for (base, mut light) in lights.iter_mut() {
+        let intensity = if bases.0[base.0].intact() { LightSource::base_light().intensity } else { 0. };
+        if light.intensity != intensity {
+            light.intensity = intensity;
+        }
+    }
+}
+
+/// The roof falls in a piece at a time as a base loses health
+pub fn show_damage(bases: Res<Bases>, mut roofs: Query<(&BaseRoof, &mut Visibility)>) {
+    if !bases.is_changed() {
+        return;
+    }
+    for (roof, mut visibility) in roofs.iter_mut() {
+        let left = (bases.0[roof.base].health / BASE_MAX_HEALTH * ROOF_PIECES as f32).ceil() as usize;
+        let shown = if roof.piece < left { Visibility::Inherited } else { Visibility::Hidden };
+        if *visibility != shown {
+            *visibility = shown;
+        }
+    }
+}
+
+/// H takes the player back to a base that's still standing
+pub fn recall(
+    keycode: Res<ButtonInput<KeyCode>>,
+    bases: Res<Bases>,
+    mut players: Query<&mut Transform, With<Player>>,
+) {
+    if !keycode.just_pressed(KeyCode::KeyH) {
+        return;
+    }
+    let Some((x, y)) = bases.respawn_point() else { return; };
+    let (x, y) = map::get_world(x, y);
+    for mut transform in players.iter_mut() {
+        transform.translation.x = x;
+        transform.translation.y = y;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim;
+
+    #[test]
+    fn hoe_mends_a_damaged_base() {
+        let mut app = sim::fresh_run();
+        let center = app.world.resource::<Bases>().0[0].center;
+        app.world.resource_mut::<Bases>().0[0].health = 50.;
+        let resources = app.world.resource::<Stockpile>().0;
+
+        app.world.send_event(ClickEvent(center, PlayerTool::Tiller));
+        app.update();
+        assert_eq!(app.world.resource::<Bases>().0[0].health, 60.);
+        assert_eq!(app.world.resource::<Stockpile>().0, resources - 1);
+    }
+
+    #[test]
+    fn fallen_base_keeps_the_stockpile() {
+        let mut app = sim::fresh_run();
+        app.world.resource_mut::<Stockpile>().0 = 200;
+        app.world.resource_mut::<Bases>().0[0].health = 0.;
+
+        app.update();
+        assert_eq!(app.world.resource::<Stockpile>().0, 200);
+        assert_eq!(app.world.resource::<Bases>().harvest_bonus(), 1);
+    }
+}
@@ -0,0 +1,210 @@
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    effects::Embers,
+    game::Stockpile,
+    lighting::{LightSource, Occluder},
+    loading::{GameAssets, TileAtlas},
+    map::{self, MapSize, TileMap, TileState},
+    pathfinding,
+    player::{ClickEvent, PlayerTool},
+    sfx::{Sfx, SfxEvent},
+    the_core,
+    walls::{self, WallTier},
+};
+
+/// Things the player can buy and place on the map
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Buildable {
+    Torch,
+    Lamp,
+    Wall(WallTier),
+}
+
+impl Buildable {
+    /// Resources it costs to place one
+    pub fn cost(&self) -> u32 {
+        match self {
+            Buildable::Torch => 2,
+            Buildable::Lamp => 5,
+            Buildable::Wall(tier) => tier.cost(),
+        }
+    }
+
+    pub fn max_health(&self) -> f32 {
+        match self {
+            Buildable::Torch => 10.,
+            Buildable::Lamp => 25.,
+            Buildable::Wall(tier) => tier.max_health(),
+        }
+    }
+
+    /// The light it gives off once placed, if any
+    pub fn light(&self) -> Option<LightSource> {
+        match self {
+            Buildable::Torch => Some(LightSource::torch()),
+            Buildable::Lamp => Some(LightSource::lamp()),
+            Buildable::Wall(_) => None,
+        }
+    }
+}
+
+/// A placed building and what it is
+#[derive(Component)]
+pub struct Building(pub Buildable);
+
+/// How much more punishment a building can take
+#[derive(Component)]
+pub struct Health(pub f32);
+
+/// Buildings on the map by tile, one per tile
+#[derive(Resource, Default)]
+pub struct Buildings(pub HashMap<(i32, i32), Entity>);
+
+impl Buildings {
+    pub fn occupied(&self, x: i32, y: i32) -> bool {
+        self.0.contains_key(&(x, y))
+    }
+}
+
+/// Place the selected building where the player clicked, if it fits and they can pay for it.
+/// Raking a building takes it down again for half its cost back.
+#[allow(clippy::too_many_arguments)]
+pub fn place_buildings(
+    mut commands: Commands,
+    mut click_events: EventReader<ClickEvent>,
+    mut buildings: ResMut<Buildings>,
+    mut stockpile: ResMut<Stockpile>,
+    placed: Query<&Building>,
+    tile_map: Res<TileMap>,
+    map_size: Res<MapSize>,
+    game_assets: Res<GameAssets>,
+    atlas: Res<TileAtlas>,
+    mut ev_sfx: EventWriter<SfxEvent>,
+) {
+    // Walls from this tick aren't spawned yet, so keep track of them for the seal check
+    let mut new_walls: Vec<(i32, i32)> = Vec::new();
+    for ev in click_events.read() {
+        let (x, y) = ev.0;
+        let kind = match ev.1 {
+            PlayerTool::Build(kind) => kind,
+            PlayerTool::Rake => {
+                if let Some(entity) = buildings.0.remove(&(x, y)) {
+                    if let Ok(building) = placed.get(entity) {
+                        stockpile.0 += building.0.cost() / 2;
+                    }
+                    commands.entity(entity).despawn_recursive();
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        let walled = |tile: (i32, i32)| new_walls.contains(&tile) || walls::is_wall(&buildings, &placed, tile);
+        if !can_place(kind, (x, y), &tile_map, &map_size, &buildings, walled) {
+            continue;
+        }
+        if stockpile.0 < kind.cost() {
+            continue;
+        }
+        stockpile.0 -= kind.cost();
+
+        let entity = spawn_building(&mut commands, &game_assets, &atlas, kind, (x, y), kind.max_health());
+        buildings.0.insert((x, y), entity);
+        if let Buildable::Wall(_) = kind {
+            new_walls.push((x, y));
+        }
+        ev_sfx.send(SfxEvent(Sfx::ToolUse));
+    }
+}
+
+/// Whether a building can go on a tile. Torches and lamps go anywhere but crops and solid
+/// tiles, walls only on open ground and never where they'd close the last way in to the core.
+pub fn can_place(
+    kind: Buildable,
+    (x, y): (i32, i32),
+    tile_map: &TileMap,
+    map_size: &MapSize,
+    buildings: &Buildings,
+    walled: impl Fn((i32, i32)) -> bool,
+) -> bool {
+    let Some(tile) = tile_map.get(x, y) else { return false; };
+    if buildings.occupied(x, y) {
+        return false;
+    }
+    match kind {
+        Buildable::Wall(_) => {
+            let core = the_core::core_tile(map_size);
+            tile.state == TileState::Untoiled && !pathfinding::seals_core(tile_map.width(), tile_map.height(), core, (x, y), walled)
+        }
+        _ => tile.state != TileState::Planted && !tile.blocks_light(),
+    }
+}
+
+/// Put a building on a tile, without paying for it or noting it in `Buildings`
+pub fn spawn_building(
+    commands: &mut Commands,
+    game_assets: &GameAssets,
+    atlas: &TileAtlas,
+    kind: Buildable,
+    (x, y): (i32, i32),
+    health: f32,
+) -> Entity {
+    let (world_x, world_y) = map::get_world(x, y);
+    let (image, color, z) = match kind {
+        Buildable::Torch => (&game_assets.torch, Color::WHITE, 1.),
+        // Lamps reuse the torch sprite, tinted to their light
+        Buildable::Lamp => (&game_assets.torch, LightSource::lamp().color, 1.),
+        // Walls get their shape from walls::connect_walls once they're up
+        Buildable::Wall(tier) => (&game_assets.base_wall, tier.color(), 2.),
+    };
+    let entity = commands.spawn((
+        SpriteSheetBundle {
+            texture: atlas.image.clone(),
+            atlas: atlas.sprite(image),
+            sprite: Sprite { color, ..default() },
+            transform: Transform::from_xyz(world_x, world_y, z),
+            ..default()
+        },
+        Building(kind),
+        Health(health),
+    )).id();
+    if let Some(light) = kind.light() {
+        commands.entity(entity).insert(light);
+    }
+    match kind {
+        Buildable::Torch => { commands.entity(entity).insert(Embers); }
+        Buildable::Wall(_) => { commands.entity(entity).insert(Occluder); }
+        Buildable::Lamp => {}
+    }
+    entity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim;
+
+    #[test]
+    fn last_wall_around_the_homestead_is_refused() {
+        let app = sim::fresh_run();
+        let tile_map = app.world.resource::<TileMap>();
+        let map_size = app.world.resource::<MapSize>();
+        let buildings = app.world.resource::<Buildings>();
+
+        // Up the left of the homestead, across the top, then down the right to the bottom edge
+        let mut ring: Vec<(i32, i32)> = (0..=18).map(|y| (18, y)).collect();
+        ring.extend((19..52).map(|x| (x, 18)));
+        ring.extend((0..=18).rev().map(|y| (52, y)));
+        let last = ring.pop().unwrap();
+
+        let mut walled = Vec::new();
+        for tile in ring {
+            assert!(can_place(Buildable::Wall(WallTier::Wood), tile, tile_map, map_size, buildings, |next| walled.contains(&next)), "{:?}", tile);
+            walled.push(tile);
+        }
+        assert!(!can_place(Buildable::Wall(WallTier::Wood), last, tile_map, map_size, buildings, |next| walled.contains(&next)));
+        // A torch doesn't block anything
+        assert!(can_place(Buildable::Torch, last, tile_map, map_size, buildings, |next| walled.contains(&next)));
+    }
+}
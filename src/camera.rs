@@ -0,0 +1,108 @@
+use bevy::{core_pipeline::{bloom::BloomSettings, tonemapping::Tonemapping}, input::mouse::MouseWheel, prelude::*};
+
+use crate::{map::MapSize, player::Player};
+
+/// Used to help identify our main camera
+#[derive(Component)]
+pub struct MainCamera;
+
+/// Tuning for how the camera chases the player
+#[derive(Resource)]
+pub struct CameraSettings {
+    /// How quickly the camera catches up to the player (higher is snappier)
+    pub follow_speed: f32,
+    /// Allow the mouse wheel to zoom the camera
+    pub zoom_enabled: bool,
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+    /// How much a single wheel notch changes the zoom
+    pub zoom_step: f32,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        CameraSettings {
+            follow_speed: 6.,
+            zoom_enabled: true,
+            min_zoom: 0.5,
+            max_zoom: 1.5,
+            zoom_step: 0.1,
+        }
+    }
+}
+
+pub fn spawn_camera(commands: &mut Commands, position: Vec2) {
+    commands.spawn(( //Camera with bloom settings enabled
+        Camera2dBundle {
+            camera: Camera {
+                hdr: true,
+                ..default()
+            },
+            tonemapping: Tonemapping::TonyMcMapface,
+            transform: Transform::from_xyz(position.x, position.y, 0.),
+            ..default()
+        },
+        BloomSettings{
+            intensity: 0.1,
+            ..default()
+        },
+        MainCamera
+    ));
+}
+
+/// Zoom the camera in and out with the mouse wheel
+pub fn camera_zoom(
+    settings: Res<CameraSettings>,
+    mut wheel: EventReader<MouseWheel>,
+    mut q_camera: Query<&mut OrthographicProjection, With<MainCamera>>,
+) {
+    if !settings.zoom_enabled {
+        wheel.clear();
+        return;
+    }
+    let Ok(mut projection) = q_camera.get_single_mut() else { return; };
+
+    for ev in wheel.read() {
+        // Scrolling up zooms in (smaller scale)
+        let scale = projection.scale - (ev.y.signum() * settings.zoom_step);
+        projection.scale = scale.clamp(settings.min_zoom, settings.max_zoom);
+    }
+}
+
+/// Smoothly follow the player, keeping the view inside the map
+pub fn camera_follow(
+    time: Res<Time>,
+    settings: Res<CameraSettings>,
+    map_size: Res<MapSize>,
+    q_player: Query<&Transform, (With<Player>, Without<MainCamera>)>,
+    mut q_camera: Query<(&mut Transform, &OrthographicProjection), With<MainCamera>>,
+) {
+    let Ok(player) = q_player.get_single() else { return; };
+    let Ok((mut transform, projection)) = q_camera.get_single_mut() else { return; };
+
+    // Exponential smoothing so the follow speed doesn't depend on frame rate
+    let t = 1. - (-settings.follow_speed * time.delta_seconds()).exp();
+    let target = transform.translation.truncate().lerp(player.translation.truncate(), t);
+
+    // The projection area already has the zoom applied
+    let clamped = clamp_to_bounds(target, projection.area.half_size(), map_size.world_bounds());
+
+    transform.translation.x = clamped.x;
+    transform.translation.y = clamped.y;
+}
+
+/// Clamp a camera center so the view stays in bounds, centering on any axis the map doesn't fill
+fn clamp_to_bounds(center: Vec2, half_view: Vec2, bounds: Rect) -> Vec2 {
+    let clamp_axis = |c: f32, half: f32, min: f32, max: f32| {
+        if max - min <= half * 2. {
+            (min + max) / 2.
+        } else {
+            c.clamp(min + half, max - half)
+        }
+    };
+
+    Vec2::new(
+        clamp_axis(center.x, half_view.x, bounds.min.x, bounds.max.x),
+        clamp_axis(center.y, half_view.y, bounds.min.y, bounds.max.y),
+    )
+}
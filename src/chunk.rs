@@ -0,0 +1,135 @@
+use bevy::{
+    prelude::*,
+    render::{mesh::{Indices, PrimitiveTopology}, render_asset::RenderAssetUsages},
+    sprite::{MaterialMesh2dBundle, Mesh2dHandle},
+    utils::HashMap,
+};
+
+use crate::{loading::{GameAssets, TileAtlas}, map::{self, TileMap, CHUNK_SIZE, TILE_SIZE}};
+
+/// A CHUNK_SIZE x CHUNK_SIZE block of tiles drawn as a single mesh
+#[derive(Component)]
+pub struct Chunk;
+
+/// Material shared by all chunks, textured with the tile atlas
+#[derive(Resource)]
+pub struct ChunkMaterial(pub Handle<ColorMaterial>);
+
+/// Chunk entities currently spawned, by chunk coordinate
+#[derive(Resource, Default)]
+pub struct ChunkEntities(pub HashMap<IVec2, Entity>);
+
+pub fn setup_material(mut commands: Commands, atlas: Res<TileAtlas>, mut materials: ResMut<Assets<ColorMaterial>>) {
+    commands.insert_resource(ChunkMaterial(materials.add(ColorMaterial {
+        color: Color::WHITE,
+        texture: Some(atlas.image.clone()),
+    })));
+}
+
+/// Rebuild the mesh of every chunk that had a tile change, spawning chunks that don't exist yet
+#[allow(clippy::too_many_arguments)]
+pub fn rebuild_dirty_chunks(
+    mut commands: Commands,
+    mut tile_map: ResMut<TileMap>,
+    game_assets: Res<GameAssets>,
+    atlas: Res<TileAtlas>,
+    material: Res<ChunkMaterial>,
+    mut chunks: ResMut<ChunkEntities>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    q_chunks: Query<&Mesh2dHandle, With<Chunk>>,
+) {
+    for coords in tile_map.take_dirty_chunks() {
+        let mesh = build_chunk_mesh(&tile_map, &game_assets, &atlas, coords);
+
+        // Swap the mesh in place when the chunk already exists
+        if let Some(handle) = chunks.0.get(&coords).and_then(|e| q_chunks.get(*e).ok()) {
+            if let Some(existing) = meshes.get_mut(&handle.0) {
+                *existing = mesh;
+                continue;
+            }
+        }
+
+        let (x, y) = map::get_world(coords.x * CHUNK_SIZE, coords.y * CHUNK_SIZE);
+        let entity = commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: Mesh2dHandle(meshes.add(mesh)),
+                material: material.0.clone(),
+                transform: Transform::from_xyz(x, y, 0.),
+                ..default()
+            },
+            Chunk,
+        )).id();
+        chunks.0.insert(coords, entity);
+    }
+}
+
+/// Push new tile brightness into the vertex colors of chunks whose lighting changed
+pub fn refresh_chunk_lighting(
+    mut tile_map: ResMut<TileMap>,
+    chunks: Res<ChunkEntities>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    q_chunks: Query<&Mesh2dHandle, With<Chunk>>,
+) {
+    for coords in tile_map.take_light_dirty_chunks() {
+        let Some(handle) = chunks.0.get(&coords).and_then(|e| q_chunks.get(*e).ok()) else { continue; };
+        if let Some(mesh) = meshes.get_mut(&handle.0) {
+            mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, chunk_colors(&tile_map, coords));
+        }
+    }
+}
+
+/// Tiles in a chunk, in the order their quads are laid out
+fn chunk_tiles(tile_map: &TileMap, coords: IVec2) -> impl Iterator<Item = (i32, i32)> {
+    let (start_x, start_y) = (coords.x * CHUNK_SIZE, coords.y * CHUNK_SIZE);
+    let end_x = (start_x + CHUNK_SIZE).min(tile_map.width());
+    let end_y = (start_y + CHUNK_SIZE).min(tile_map.height());
+    (start_y..end_y).flat_map(move |y| (start_x..end_x).map(move |x| (x, y)))
+}
+
+fn chunk_colors(tile_map: &TileMap, coords: IVec2) -> Vec<[f32; 4]> {
+    chunk_tiles(tile_map, coords)
+        .flat_map(|(x, y)| {
+            let light = tile_map.light(x, y);
+            [[light.x, light.y, light.z, 1.]; 4]
+        })
+        .collect()
+}
+
+fn build_chunk_mesh(tile_map: &TileMap, game_assets: &GameAssets, atlas: &TileAtlas, coords: IVec2) -> Mesh {
+    let half = TILE_SIZE / 2.;
+    let mut positions = Vec::new();
+    let mut uvs = Vec::new();
+    let mut indices = Vec::new();
+
+    for (x, y) in chunk_tiles(tile_map, coords) {
+        let Some(tile) = tile_map.get(x, y) else { continue; };
+        let uv = game_assets.tiles.get(&tile.kind).map(|image| atlas.uv(image)).unwrap_or_default();
+
+        // Quad centered on the tile, relative to the chunk origin
+        let local_x = (x - coords.x * CHUNK_SIZE) as f32 * TILE_SIZE;
+        let local_y = (y - coords.y * CHUNK_SIZE) as f32 * TILE_SIZE;
+        let base = positions.len() as u32;
+
+        positions.extend([
+            [local_x - half, local_y - half, 0.],
+            [local_x + half, local_y - half, 0.],
+            [local_x + half, local_y + half, 0.],
+            [local_x - half, local_y + half, 0.],
+        ]);
+        // Image v runs top to bottom
+        uvs.extend([
+            [uv.min.x, uv.max.y],
+            [uv.max.x, uv.max.y],
+            [uv.max.x, uv.min.y],
+            [uv.min.x, uv.min.y],
+        ]);
+        indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default());
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, chunk_colors(tile_map, coords));
+    mesh.insert_indices(Indices::U32(indices));
+    mesh
+}
@@ -0,0 +1,326 @@
+//! Developer console, only built with `--features dev`. Backtick opens it.
+//! Modules add their own commands with `ConsoleApp::add_console_command`.
+
+use bevy::{ecs::event::ManualEventReader, input::InputSystem, prelude::*, utils::HashMap, window::ReceivedCharacter};
+
+use crate::{
+    day_night::{DayCount, DayNightCycle, DayNightSchedule, DayPhase, PhaseChangedEvent},
+    enemies::{EnemyBundle, EnemyKind},
+    game::{Stockpile, TickSet},
+    loading::GameAssets,
+    map::{Tile, TileKind, TileMap, TileState},
+    replay::{ConsoleLine, ReplayPlayback},
+    run::Run,
+    the_core::{CoreHealth, GodMode},
+};
+
+/// Lines of output kept on screen
+const LOG_LINES: usize = 12;
+/// Commands remembered for the up and down keys
+const HISTORY_LENGTH: usize = 50;
+
+/// A command gets the world and its arguments, and says what it did or what went wrong
+pub type CommandFn = fn(&mut World, &[&str]) -> Result<String, String>;
+
+pub struct ConsoleCommand {
+    pub usage: &'static str,
+    pub run: CommandFn,
+}
+
+/// Every command the console knows, by name
+#[derive(Resource, Default)]
+pub struct ConsoleCommands(HashMap<&'static str, ConsoleCommand>);
+
+pub trait ConsoleApp {
+    fn add_console_command(&mut self, name: &'static str, usage: &'static str, run: CommandFn) -> &mut Self;
+}
+
+impl ConsoleApp for App {
+    fn add_console_command(&mut self, name: &'static str, usage: &'static str, run: CommandFn) -> &mut Self {
+        self.world.get_resource_or_insert_with(ConsoleCommands::default).0.insert(name, ConsoleCommand { usage, run });
+        self
+    }
+}
+
+/// What's typed, what's been said, and what's waiting to run
+#[derive(Resource, Default)]
+pub struct Console {
+    open: bool,
+    input: String,
+    log: Vec<String>,
+    history: Vec<String>,
+    /// Where up and down have got to in the history, None while typing something new
+    browsing: Option<usize>,
+    pending: Vec<String>,
+}
+
+impl Console {
+    fn print(&mut self, line: impl Into<String>) {
+        self.log.push(line.into());
+        let extra = self.log.len().saturating_sub(LOG_LINES);
+        self.log.drain(..extra);
+    }
+}
+
+#[derive(Component)]
+struct ConsoleUi;
+
+#[derive(Component)]
+struct ConsoleText;
+
+pub fn build_plugin(app: &mut App) {
+    app
+        .init_resource::<Console>()
+        .init_resource::<ConsoleCommands>()
+        .add_systems(PreUpdate, console_input.after(InputSystem))
+        // Commands go in with the tick's other inputs so replays can run them again
+        .add_systems(FixedUpdate, (
+            submit_commands.in_set(TickSet::Input).run_if(not(resource_exists::<ReplayPlayback>)),
+            run_commands.after(TickSet::Record).before(TickSet::Simulate),
+        ))
+        .add_systems(Update, show_console)
+        .add_console_command("help", "help", help)
+        .add_console_command("give", "give resources <amount>", give)
+        .add_console_command("set_time", "set_time <dawn|day|dusk|night>", set_time)
+        .add_console_command("spawn", "spawn enemy <crawler|brute> <x> <y>", spawn)
+        .add_console_command("core_hp", "core_hp <health>", core_hp)
+        .add_console_command("tile", "tile <x> <y> <untoiled|toiled|planted|immutable>", tile)
+        .add_console_command("god", "god", god)
+        .add_console_command("seed", "seed", seed);
+}
+
+/// Typing goes to the console while it's open, and the game doesn't see any of the keys
+fn console_input(
+    mut console: ResMut<Console>,
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+    mut ev_chars: EventReader<ReceivedCharacter>,
+    commands: Res<ConsoleCommands>,
+) {
+    if keys.just_pressed(KeyCode::Backquote) {
+        console.open = !console.open;
+        keys.reset_all();
+        ev_chars.clear();
+        return;
+    }
+    if !console.open {
+        ev_chars.clear();
+        return;
+    }
+
+    for ev in ev_chars.read() {
+        let typed: String = ev.char.chars().filter(|c| !c.is_control() && *c != '`').collect();
+        console.input.push_str(&typed);
+    }
+
+    if keys.just_pressed(KeyCode::Backspace) {
+        console.input.pop();
+    }
+    if keys.just_pressed(KeyCode::Enter) {
+        let line = std::mem::take(&mut console.input).trim().to_string();
+        if !line.is_empty() {
+            console.history.retain(|old| *old != line);
+            console.history.push(line.clone());
+            let extra = console.history.len().saturating_sub(HISTORY_LENGTH);
+            console.history.drain(..extra);
+            console.pending.push(line);
+        }
+        console.browsing = None;
+    }
+    if keys.just_pressed(KeyCode::ArrowUp) && !console.history.is_empty() {
+        let i = console.browsing.map_or(console.history.len() - 1, |i| i.saturating_sub(1));
+        console.browsing = Some(i);
+        console.input = console.history[i].clone();
+    }
+    if keys.just_pressed(KeyCode::ArrowDown) {
+        if let Some(i) = console.browsing {
+            let next = i + 1;
+            console.browsing = (next < console.history.len()).then_some(next);
+            console.input = console.history.get(next).cloned().unwrap_or_default();
+        }
+    }
+    if keys.just_pressed(KeyCode::Tab) {
+        complete(&mut console, &commands);
+    }
+
+    keys.reset_all();
+}
+
+/// Finish the command name, or list the ones it could be
+fn complete(console: &mut Console, commands: &ConsoleCommands) {
+    if console.input.contains(' ') {
+        return;
+    }
+    let mut matches: Vec<&str> = commands.0.keys().copied().filter(|name| name.starts_with(console.input.as_str())).collect();
+    matches.sort();
+    match matches.as_slice() {
+        [] => {}
+        [name] => console.input = format!("{} ", name),
+        _ => {
+            let line = matches.join("  ");
+            console.print(line);
+        }
+    }
+}
+
+/// Hand what's been entered to this tick, as it would be typed in a replay
+fn submit_commands(mut console: ResMut<Console>, mut ev_console: EventWriter<ConsoleLine>) {
+    for line in std::mem::take(&mut console.pending) {
+        ev_console.send(ConsoleLine(line));
+    }
+}
+
+/// Commands need the whole world, so they run here rather than as they're typed
+fn run_commands(world: &mut World, mut reader: Local<ManualEventReader<ConsoleLine>>) {
+    let lines: Vec<String> = reader.read(world.resource::<Events<ConsoleLine>>()).map(|ev| ev.0.clone()).collect();
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((name, args)) = words.split_first() else { continue; };
+        let command = world.resource::<ConsoleCommands>().0.get(name).map(|command| (command.run, command.usage));
+        let reply = match command {
+            Some((run, usage)) => match run(world, args) {
+                Ok(reply) => reply,
+                Err(err) => format!("{}\n  usage: {}", err, usage),
+            },
+            None => format!("Unknown command {}, try help", name),
+        };
+        let mut console = world.resource_mut::<Console>();
+        console.print(format!("> {}", line));
+        for reply_line in reply.lines() {
+            console.print(reply_line.to_string());
+        }
+    }
+}
+
+/// Spawn the console when it opens, and keep it around even when a screen cleans up its UI
+fn show_console(
+    mut commands: Commands,
+    console: Res<Console>,
+    game_assets: Option<Res<GameAssets>>,
+    ui: Query<Entity, With<ConsoleUi>>,
+    mut text: Query<&mut Text, With<ConsoleText>>,
+) {
+    if !console.open {
+        for entity in ui.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    }
+
+    let mut lines = console.log.clone();
+    lines.push(format!("> {}_", console.input));
+    let value = lines.join("\n");
+
+    if let Ok(mut text) = text.get_single_mut() {
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+        return;
+    }
+    let font = game_assets.map(|assets| assets.ui_font.clone()).unwrap_or_default();
+    commands.spawn((ConsoleUi, NodeBundle {
+        style: Style {
+            width: Val::Percent(100.0),
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(0.0),
+            padding: UiRect::all(Val::Px(8.0)),
+            ..default()
+        },
+        background_color: Color::rgba(0., 0., 0., 0.8).into(),
+        z_index: ZIndex::Global(100),
+        ..default()
+    })).with_children(|parent| {
+        parent.spawn((ConsoleText, TextBundle::from_section(value, TextStyle { font, font_size: 18.0, color: Color::WHITE })));
+    });
+}
+
+fn parse<T: std::str::FromStr>(arg: Option<&&str>, what: &str) -> Result<T, String> {
+    let arg = arg.ok_or_else(|| format!("Missing {}", what))?;
+    arg.parse().map_err(|_| format!("Bad {}: {}", what, arg))
+}
+
+fn help(world: &mut World, _: &[&str]) -> Result<String, String> {
+    let mut usages: Vec<&str> = world.resource::<ConsoleCommands>().0.values().map(|command| command.usage).collect();
+    usages.sort();
+    Ok(usages.join("\n"))
+}
+
+fn give(world: &mut World, args: &[&str]) -> Result<String, String> {
+    if args.first() != Some(&"resources") {
+        return Err("Can only give resources".into());
+    }
+    let amount: u32 = parse(args.get(1), "amount")?;
+    let mut stockpile = world.get_resource_mut::<Stockpile>().ok_or("Not in a run")?;
+    stockpile.0 = stockpile.0.saturating_add(amount);
+    Ok(format!("Stockpile is now {}", stockpile.0))
+}
+
+fn set_time(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let phase = match args.first().copied() {
+        Some("dawn") => DayPhase::Dawn,
+        Some("day") => DayPhase::Day,
+        Some("dusk") => DayPhase::Dusk,
+        Some("night") => DayPhase::Night,
+        _ => return Err("Which phase?".into()),
+    };
+    if !world.contains_resource::<DayNightCycle>() {
+        return Err("Not in a run".into());
+    }
+    world.resource_scope(|world, mut cycle: Mut<DayNightCycle>| {
+        world.resource_scope(|world, mut day: Mut<DayCount>| {
+            let events = cycle.skip_to(world.resource::<DayNightSchedule>(), &mut day, phase);
+            world.send_event_batch::<PhaseChangedEvent>(events);
+        });
+    });
+    Ok(format!("It's now {:?}", phase))
+}
+
+fn spawn(world: &mut World, args: &[&str]) -> Result<String, String> {
+    if args.first() != Some(&"enemy") {
+        return Err("Can only spawn enemies".into());
+    }
+    let name = args.get(1).ok_or("Missing type")?;
+    let kind = EnemyKind::ALL.into_iter().find(|kind| kind.name() == *name).ok_or_else(|| format!("No enemy called {}", name))?;
+    let x: i32 = parse(args.get(2), "x")?;
+    let y: i32 = parse(args.get(3), "y")?;
+    let tile_map = world.get_resource::<TileMap>().ok_or("Not in a run")?;
+    if tile_map.get(x, y).is_none() {
+        return Err(format!("{}, {} is off the map", x, y));
+    }
+    world.spawn(EnemyBundle::new(kind, (x, y)));
+    Ok(format!("A {} is coming from {}, {}", name, x, y))
+}
+
+fn core_hp(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let health: f32 = parse(args.first(), "health")?;
+    let mut core = world.get_resource_mut::<CoreHealth>().ok_or("Not in a run")?;
+    core.0 = health;
+    Ok(format!("Core health is now {}", health))
+}
+
+fn tile(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let x: i32 = parse(args.first(), "x")?;
+    let y: i32 = parse(args.get(1), "y")?;
+    let tile = match args.get(2).copied() {
+        Some("untoiled") => Tile::new(TileKind::RedGrass, TileState::Untoiled),
+        Some("toiled") => Tile::new(TileKind::FarmTile, TileState::Toiled),
+        Some("planted") => Tile::new(TileKind::SeedsGreen, TileState::Planted),
+        Some("immutable") => Tile::new(TileKind::Concrete, TileState::Immutable),
+        _ => return Err("Which state?".into()),
+    };
+    let mut tile_map = world.get_resource_mut::<TileMap>().ok_or("Not in a run")?;
+    if tile_map.get(x, y).is_none() {
+        return Err(format!("{}, {} is off the map", x, y));
+    }
+    tile_map.set(x, y, tile);
+    Ok(format!("{}, {} is now {:?}", x, y, tile.state))
+}
+
+fn god(world: &mut World, _: &[&str]) -> Result<String, String> {
+    let mut god = world.resource_mut::<GodMode>();
+    god.0 = !god.0;
+    Ok(format!("God mode {}", if god.0 { "on" } else { "off" }))
+}
+
+fn seed(world: &mut World, _: &[&str]) -> Result<String, String> {
+    Ok(format!("Run seed is {}", world.resource::<Run>().seed))
+}
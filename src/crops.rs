@@ -0,0 +1,86 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{
+    lighting::{self, LightGlow, LightingPhase},
+    map::{Tile, TileKind, TileMap, TileState},
+    weather::{Weather, WeatherKind},
+};
+
+/// Seconds of growing a crop needs before it can be harvested
+const GROW_TIME: f32 = 45.;
+/// Resources a ripe crop gives when raked up
+pub const HARVEST_YIELD: u32 = 2;
+/// Glow a light hungry crop needs to keep growing after dark
+const NIGHT_GROW_GLOW: f32 = 0.3;
+/// Glow that keeps a crop warm through a cold snap
+const WARM_GLOW: f32 = 0.3;
+/// Seconds out in the cold before a crop wilts
+const WILT_TIME: f32 = 20.;
+
+/// A planted tile's progress
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Crop {
+    /// 0 when planted and 1 when ripe
+    pub growth: f32,
+    /// Seconds spent unlit in a cold snap
+    pub chill: f32,
+}
+
+/// Every planted tile's crop
+#[derive(Resource, Default)]
+pub struct Crops(pub HashMap<(i32, i32), Crop>);
+
+impl Crops {
+    pub fn is_ripe(&self, x: i32, y: i32) -> bool {
+        self.0.get(&(x, y)).is_some_and(|crop| crop.growth >= 1.)
+    }
+}
+
+/// Pink seeds only grow at night when a light reaches them
+fn needs_light(kind: TileKind) -> bool {
+    kind == TileKind::SeedsPink
+}
+
+/// Grow planted tiles, tracking new plantings and forgetting cleared ones.
+/// Weather speeds growth up or, in a cold snap, wilts crops left in the dark.
+pub fn grow_crops(
+    time: Res<Time>,
+    phase: Res<LightingPhase>,
+    light_glow: Res<LightGlow>,
+    weather: Res<Weather>,
+    mut tile_map: ResMut<TileMap>,
+    mut crops: ResMut<Crops>,
+) {
+    crops.0.retain(|(x, y), _| tile_map.get(*x, *y).is_some_and(|tile| tile.state == TileState::Planted));
+
+    let is_night = phase.night >= 0.5;
+    let mut wilted = Vec::new();
+    for x in 0..tile_map.width() {
+        for y in 0..tile_map.height() {
+            let Some(tile) = tile_map.get(x, y) else { continue; };
+            if tile.state != TileState::Planted {
+                continue;
+            }
+            let glow = lighting::glow_at(&light_glow, &phase, x, y);
+            let crop = crops.0.entry((x, y)).or_default();
+
+            if weather.kind == WeatherKind::ColdSnap && glow < WARM_GLOW {
+                crop.chill += time.delta_seconds();
+                if crop.chill >= WILT_TIME {
+                    wilted.push((x, y));
+                }
+                continue;
+            }
+            if is_night && needs_light(tile.kind) && glow < NIGHT_GROW_GLOW {
+                continue;
+            }
+            crop.growth = (crop.growth + weather.kind.growth_factor() * time.delta_seconds() / GROW_TIME).min(1.);
+        }
+    }
+
+    // Wilted crops are lost, leaving the tilled soil behind
+    for (x, y) in wilted {
+        crops.0.remove(&(x, y));
+        tile_map.set(x, y, Tile::new(TileKind::FarmTile, TileState::Toiled));
+    }
+}
@@ -0,0 +1,336 @@
+use bevy::prelude::*;
+
+/// The named parts of a day, in the order they happen
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum DayPhase {
+    Dawn,
+    Day,
+    Dusk,
+    Night,
+}
+
+impl DayPhase {
+    /// The phase that follows this one, night wraps back around to dawn
+    pub fn next(&self) -> DayPhase {
+        match self {
+            DayPhase::Dawn => DayPhase::Day,
+            DayPhase::Day => DayPhase::Dusk,
+            DayPhase::Dusk => DayPhase::Night,
+            DayPhase::Night => DayPhase::Dawn,
+        }
+    }
+}
+
+/// How long each phase of the day lasts, in seconds
+#[derive(Resource, Clone, Copy, PartialEq, Debug)]
+pub struct DayNightSchedule {
+    pub dawn: f32,
+    pub day: f32,
+    pub dusk: f32,
+    pub night: f32,
+}
+
+impl Default for DayNightSchedule {
+    fn default() -> Self {
+        // A minute long day, mostly night
+        DayNightSchedule { dawn: 6., day: 15., dusk: 6., night: 33. }
+    }
+}
+
+impl DayNightSchedule {
+    pub fn duration(&self, phase: DayPhase) -> f32 {
+        match phase {
+            DayPhase::Dawn => self.dawn,
+            DayPhase::Day => self.day,
+            DayPhase::Dusk => self.dusk,
+            DayPhase::Night => self.night,
+        }
+    }
+
+    pub fn day_length(&self) -> f32 {
+        self.dawn + self.day + self.dusk + self.night
+    }
+}
+
+/// Days survived so far, the first day is day 1
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DayCount(pub u32);
+
+impl Default for DayCount {
+    fn default() -> Self {
+        DayCount(1)
+    }
+}
+
+/// Sent whenever the day moves into a new phase
+#[derive(Event, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PhaseChangedEvent {
+    pub from: DayPhase,
+    pub to: DayPhase,
+    /// Day the new phase belongs to
+    pub day: u32,
+}
+
+/// Where we are in the current day
+#[derive(Resource, Clone, Copy, PartialEq, Debug)]
+pub struct DayNightCycle {
+    pub phase: DayPhase,
+    /// Seconds spent in the current phase
+    pub elapsed: f32,
+}
+
+impl Default for DayNightCycle {
+    fn default() -> Self {
+        DayNightCycle { phase: DayPhase::Dawn, elapsed: 0. }
+    }
+}
+
+impl DayNightCycle {
+    /// How far through the current phase we are, 0.0 - 1.0
+    pub fn progress(&self, schedule: &DayNightSchedule) -> f32 {
+        let duration = schedule.duration(self.phase);
+        if duration <= 0. {
+            return 1.;
+        }
+        (self.elapsed / duration).min(1.)
+    }
+
+    /// Move the clock forward, returning every phase change along the way.
+    /// Large steps can cross several phases, or even whole days, at once.
+    pub fn advance(&mut self, schedule: &DayNightSchedule, day: &mut DayCount, delta: f32) -> Vec<PhaseChangedEvent> {
+        let mut events = Vec::new();
+        // A day with no length would never leave its phase
+        if schedule.day_length() <= 0. {
+            return events;
+        }
+
+        self.elapsed += delta;
+        while self.elapsed >= schedule.duration(self.phase) {
+            self.elapsed -= schedule.duration(self.phase);
+            let to = self.phase.next();
+            // A new day starts at dawn
+            if to == DayPhase::Dawn {
+                day.0 += 1;
+            }
+            events.push(PhaseChangedEvent { from: self.phase, to, day: day.0 });
+            self.phase = to;
+        }
+        events
+    }
+
+    /// Jump straight to the start of the next `target` phase, reporting the
+    /// phases passed on the way. Does nothing if we're already in it.
+    pub fn skip_to(&mut self, schedule: &DayNightSchedule, day: &mut DayCount, target: DayPhase) -> Vec<PhaseChangedEvent> {
+        let mut events = Vec::new();
+        // A phase with no length can't be stopped in
+        if schedule.duration(target) <= 0. {
+            return events;
+        }
+        while self.phase != target {
+            self.elapsed = schedule.duration(self.phase);
+            let passed = self.advance(schedule, day, 0.);
+            // Nothing moves on an empty schedule
+            if passed.is_empty() {
+                break;
+            }
+            events.extend(passed);
+        }
+        events
+    }
+}
+
+/// Game speeds the speed control steps through
+pub const GAME_SPEEDS: [f32; 3] = [1., 2., 3.];
+
+/// Player requests to bend time
+#[derive(Event, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimeControlEvent {
+    /// Step to the next game speed, wrapping back to 1x
+    CycleSpeed,
+    /// Done for the day, jump ahead to dusk
+    ReadyForNight,
+}
+
+pub fn update_day_night_cycle(
+    time: Res<Time>,
+    schedule: Res<DayNightSchedule>,
+    mut cycle: ResMut<DayNightCycle>,
+    mut day: ResMut<DayCount>,
+    mut ev_phase: EventWriter<PhaseChangedEvent>,
+) {
+    let events = cycle.advance(&schedule, &mut day, time.delta_seconds());
+    ev_phase.send_batch(events);
+}
+
+/// Change game speed or skip ahead. Speed goes through virtual time, so every
+/// system reading `Time` (movement, growth, timers) speeds up together.
+pub fn apply_time_controls(
+    mut ev_controls: EventReader<TimeControlEvent>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    schedule: Res<DayNightSchedule>,
+    mut cycle: ResMut<DayNightCycle>,
+    mut day: ResMut<DayCount>,
+    mut ev_phase: EventWriter<PhaseChangedEvent>,
+) {
+    for ev in ev_controls.read() {
+        match ev {
+            TimeControlEvent::CycleSpeed => {
+                let current = virtual_time.relative_speed();
+                let next = GAME_SPEEDS.iter()
+                    .position(|speed| *speed == current)
+                    .map_or(GAME_SPEEDS[0], |i| GAME_SPEEDS[(i + 1) % GAME_SPEEDS.len()]);
+                virtual_time.set_relative_speed(next);
+            }
+            TimeControlEvent::ReadyForNight => {
+                // Only the daylight hours can be skipped
+                if matches!(cycle.phase, DayPhase::Dawn | DayPhase::Day) {
+                    let events = cycle.skip_to(&schedule, &mut day, DayPhase::Dusk);
+                    ev_phase.send_batch(events);
+                }
+            }
+        }
+    }
+}
+
+/// Keyboard shortcuts for the time controls
+pub fn time_control_keys(keycode: Res<ButtonInput<KeyCode>>, mut ev_controls: EventWriter<TimeControlEvent>) {
+    if keycode.just_pressed(KeyCode::KeyT) {
+        ev_controls.send(TimeControlEvent::CycleSpeed);
+    }
+    if keycode.just_pressed(KeyCode::KeyN) {
+        ev_controls.send(TimeControlEvent::ReadyForNight);
+    }
+}
+
+/// Leaving a run puts time back to normal speed, and running if a replay had it paused
+pub fn reset_game_speed(mut virtual_time: ResMut<Time<Virtual>>) {
+    virtual_time.set_relative_speed(1.);
+    virtual_time.unpause();
+}
+
+pub fn handle_day_night_events(mut ev_phase: EventReader<PhaseChangedEvent>) {
+    for ev in ev_phase.read() {
+        debug!("{:?} of day {}", ev.to, ev.day);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Step the clock like a 60 fps game would, collecting the events
+    fn run_for(cycle: &mut DayNightCycle, schedule: &DayNightSchedule, day: &mut DayCount, seconds: f32) -> Vec<PhaseChangedEvent> {
+        let step: f32 = 1. / 60.;
+        let mut events = Vec::new();
+        let mut remaining = seconds;
+        while remaining > 0. {
+            let delta = step.min(remaining);
+            events.extend(cycle.advance(schedule, day, delta));
+            remaining -= delta;
+        }
+        events
+    }
+
+    #[test]
+    fn starts_at_dawn_of_day_one() {
+        let cycle = DayNightCycle::default();
+        assert_eq!(cycle.phase, DayPhase::Dawn);
+        assert_eq!(DayCount::default(), DayCount(1));
+    }
+
+    #[test]
+    fn phases_follow_the_schedule() {
+        let schedule = DayNightSchedule { dawn: 1., day: 2., dusk: 1., night: 4. };
+        let mut cycle = DayNightCycle::default();
+        let mut day = DayCount::default();
+
+        run_for(&mut cycle, &schedule, &mut day, 0.5);
+        assert_eq!(cycle.phase, DayPhase::Dawn);
+        run_for(&mut cycle, &schedule, &mut day, 1.);
+        assert_eq!(cycle.phase, DayPhase::Day);
+        run_for(&mut cycle, &schedule, &mut day, 2.);
+        assert_eq!(cycle.phase, DayPhase::Dusk);
+        run_for(&mut cycle, &schedule, &mut day, 1.);
+        assert_eq!(cycle.phase, DayPhase::Night);
+        assert_eq!(day, DayCount(1));
+    }
+
+    #[test]
+    fn counts_days_over_several_cycles() {
+        let schedule = DayNightSchedule::default();
+        let mut cycle = DayNightCycle::default();
+        let mut day = DayCount::default();
+
+        // Three full days and a bit, landing in the middle of day four's dawn
+        let events = run_for(&mut cycle, &schedule, &mut day, schedule.day_length() * 3. + 1.);
+
+        assert_eq!(day, DayCount(4));
+        assert_eq!(cycle.phase, DayPhase::Dawn);
+        assert_eq!(events.len(), 12);
+
+        let dawns: Vec<u32> = events.iter().filter(|ev| ev.to == DayPhase::Dawn).map(|ev| ev.day).collect();
+        assert_eq!(dawns, vec![2, 3, 4]);
+
+        // Every event picks up where the last one left off
+        for pair in events.windows(2) {
+            assert_eq!(pair[0].to, pair[1].from);
+        }
+    }
+
+    #[test]
+    fn one_big_step_reports_every_phase() {
+        let schedule = DayNightSchedule { dawn: 1., day: 1., dusk: 1., night: 1. };
+        let mut cycle = DayNightCycle::default();
+        let mut day = DayCount::default();
+
+        let events = cycle.advance(&schedule, &mut day, 9.5);
+
+        assert_eq!(events.len(), 9);
+        assert_eq!(events.last(), Some(&PhaseChangedEvent { from: DayPhase::Dawn, to: DayPhase::Day, day: 3 }));
+        assert_eq!(cycle.phase, DayPhase::Day);
+        assert_eq!(day, DayCount(3));
+        assert!((cycle.elapsed - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn skips_phases_with_no_length() {
+        let schedule = DayNightSchedule { dawn: 0., day: 1., dusk: 0., night: 1. };
+        let mut cycle = DayNightCycle::default();
+        let mut day = DayCount::default();
+
+        let events = cycle.advance(&schedule, &mut day, 0.);
+        assert_eq!(events, vec![PhaseChangedEvent { from: DayPhase::Dawn, to: DayPhase::Day, day: 1 }]);
+
+        cycle.advance(&schedule, &mut day, 1.5);
+        assert_eq!(cycle.phase, DayPhase::Night);
+    }
+
+    #[test]
+    fn ready_for_night_skips_to_dusk() {
+        let schedule = DayNightSchedule::default();
+        let mut cycle = DayNightCycle::default();
+        let mut day = DayCount::default();
+
+        run_for(&mut cycle, &schedule, &mut day, 2.);
+        let events = cycle.skip_to(&schedule, &mut day, DayPhase::Dusk);
+
+        assert_eq!(events, vec![
+            PhaseChangedEvent { from: DayPhase::Dawn, to: DayPhase::Day, day: 1 },
+            PhaseChangedEvent { from: DayPhase::Day, to: DayPhase::Dusk, day: 1 },
+        ]);
+        assert_eq!(cycle, DayNightCycle { phase: DayPhase::Dusk, elapsed: 0. });
+
+        // Already there, nothing to skip
+        assert!(cycle.skip_to(&schedule, &mut day, DayPhase::Dusk).is_empty());
+    }
+
+    #[test]
+    fn empty_schedule_stands_still() {
+        let schedule = DayNightSchedule { dawn: 0., day: 0., dusk: 0., night: 0. };
+        let mut cycle = DayNightCycle::default();
+        let mut day = DayCount::default();
+
+        assert!(cycle.advance(&schedule, &mut day, 10.).is_empty());
+        assert_eq!(cycle, DayNightCycle::default());
+    }
+}
@@ -0,0 +1,242 @@
+//! Debug overlays, only built with `--features dev`. F1 to F6 toggle them,
+//! or `overlay <name>` from the console. The paths overlay shows the way enemies
+//! would take to the core from the closest way in on each edge, and from every enemy out.
+
+use bevy::{
+    diagnostic::{DiagnosticPath, DiagnosticsStore, EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin},
+    prelude::*,
+};
+
+use crate::{
+    bases::Bases,
+    building::Building,
+    camera::MainCamera,
+    console::ConsoleApp,
+    enemies::{CorePaths, Enemy},
+    game::MyWorldCoords,
+    lighting::LightSource,
+    loading::GameAssets,
+    map::{self, TileMap, TileState, TILE_SIZE},
+    pathfinding,
+    player::Player,
+    the_core::Core,
+    AppState,
+};
+
+/// Which overlays are showing
+#[derive(Resource, Default)]
+pub struct DebugOverlays {
+    /// Every tile on screen outlined in its state's color
+    pub grid: bool,
+    /// Tile under the cursor and what's in it
+    pub cursor: bool,
+    /// How far each light reaches
+    pub lights: bool,
+    /// Outlines what takes up space: the player, the core, buildings and the bases' hit areas
+    pub hitboxes: bool,
+    /// Frame rate and entity count
+    pub stats: bool,
+    /// Where enemies would walk to reach the core
+    pub paths: bool,
+}
+
+impl DebugOverlays {
+    fn toggle(&mut self, name: &str) -> Option<bool> {
+        let overlay = match name {
+            "grid" => &mut self.grid,
+            "cursor" => &mut self.cursor,
+            "lights" => &mut self.lights,
+            "hitboxes" => &mut self.hitboxes,
+            "stats" => &mut self.stats,
+            "paths" => &mut self.paths,
+            _ => return None,
+        };
+        *overlay = !*overlay;
+        Some(*overlay)
+    }
+}
+
+#[derive(Component)]
+struct DebugPanel;
+
+pub fn build_plugin(app: &mut App) {
+    app
+        .add_plugins((FrameTimeDiagnosticsPlugin, EntityCountDiagnosticsPlugin))
+        .init_resource::<DebugOverlays>()
+        .add_systems(Update, toggle_overlays)
+        .add_systems(Update, (
+            draw_grid.run_if(|overlays: Res<DebugOverlays>| overlays.grid),
+            draw_lights.run_if(|overlays: Res<DebugOverlays>| overlays.lights),
+            draw_hitboxes.run_if(|overlays: Res<DebugOverlays>| overlays.hitboxes),
+            draw_paths.run_if(|overlays: Res<DebugOverlays>| overlays.paths),
+            show_panel,
+        ).run_if(in_state(AppState::Game)))
+        .add_console_command("overlay", "overlay <grid|cursor|lights|hitboxes|stats|paths>", overlay);
+}
+
+fn toggle_overlays(keycode: Res<ButtonInput<KeyCode>>, mut overlays: ResMut<DebugOverlays>) {
+    let keys = [
+        (KeyCode::F1, "grid"),
+        (KeyCode::F2, "cursor"),
+        (KeyCode::F3, "lights"),
+        (KeyCode::F4, "hitboxes"),
+        (KeyCode::F5, "stats"),
+        (KeyCode::F6, "paths"),
+    ];
+    for (key, name) in keys {
+        if keycode.just_pressed(key) {
+            overlays.toggle(name);
+        }
+    }
+}
+
+fn overlay(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let name = args.first().ok_or("Which overlay?")?;
+    let on = world.resource_mut::<DebugOverlays>().toggle(name).ok_or_else(|| format!("No overlay called {}", name))?;
+    Ok(format!("{} overlay {}", name, if on { "on" } else { "off" }))
+}
+
+fn state_color(state: TileState) -> Color {
+    match state {
+        TileState::Immutable => Color::GRAY,
+        TileState::Untoiled => Color::rgb(0.8, 0.3, 0.3),
+        TileState::Toiled => Color::rgb(0.8, 0.6, 0.2),
+        TileState::Planted => Color::GREEN,
+    }
+}
+
+/// Only the tiles the camera can see, the whole map is a lot of lines
+fn draw_grid(
+    mut gizmos: Gizmos,
+    tile_map: Res<TileMap>,
+    camera: Query<(&Transform, &OrthographicProjection), With<MainCamera>>,
+) {
+    let Ok((transform, projection)) = camera.get_single() else { return; };
+    let center = transform.translation.truncate();
+    let (min_x, min_y) = map::get_tile(center.x + projection.area.min.x, center.y + projection.area.min.y);
+    let (max_x, max_y) = map::get_tile(center.x + projection.area.max.x, center.y + projection.area.max.y);
+    for x in min_x.max(0)..=max_x.min(tile_map.width() - 1) {
+        for y in min_y.max(0)..=max_y.min(tile_map.height() - 1) {
+            let Some(tile) = tile_map.get(x, y) else { continue; };
+            let (world_x, world_y) = map::get_world(x, y);
+            gizmos.rect_2d(Vec2::new(world_x, world_y), 0., Vec2::splat(TILE_SIZE - 2.), state_color(tile.state));
+        }
+    }
+}
+
+fn draw_lights(mut gizmos: Gizmos, lights: Query<(&LightSource, &GlobalTransform)>) {
+    for (light, transform) in lights.iter() {
+        gizmos.circle_2d(transform.translation().truncate(), light.radius * TILE_SIZE, light.color);
+    }
+}
+
+fn draw_hitboxes(
+    mut gizmos: Gizmos,
+    players: Query<&GlobalTransform, With<Player>>,
+    cores: Query<&GlobalTransform, With<Core>>,
+    buildings: Query<&GlobalTransform, With<Building>>,
+    bases: Res<Bases>,
+) {
+    let tile = Vec2::splat(TILE_SIZE);
+    for transform in players.iter() {
+        gizmos.rect_2d(transform.translation().truncate(), 0., tile, Color::CYAN);
+    }
+    for transform in cores.iter() {
+        gizmos.rect_2d(transform.translation().truncate(), 0., tile * 2., Color::BLUE);
+    }
+    for transform in buildings.iter() {
+        gizmos.rect_2d(transform.translation().truncate(), 0., tile, Color::ORANGE);
+    }
+    for base in bases.0.iter() {
+        let (x, y) = map::get_world(base.center.0, base.center.1);
+        let color = if base.intact() { Color::YELLOW } else { Color::RED };
+        gizmos.rect_2d(Vec2::new(x, y), 0., tile * Vec2::new(7., 3.), color);
+    }
+}
+
+/// Paths from the closest entry on the left, right and top edges in red, and each enemy's in orange
+fn draw_paths(
+    mut gizmos: Gizmos,
+    tile_map: Res<TileMap>,
+    paths: Res<CorePaths>,
+    enemies: Query<&Transform, With<Enemy>>,
+) {
+    let (width, height) = (tile_map.width(), tile_map.height());
+    // Corners count as the left or right edge
+    let side = |(x, _): (i32, i32)| if x == 0 { 0 } else if x == width - 1 { 1 } else { 2 };
+    let mut closest: [Option<(u32, (i32, i32))>; 3] = [None; 3];
+    for tile in pathfinding::entries(width, height) {
+        let Some(steps) = paths.0.steps(tile) else { continue; };
+        let best = &mut closest[side(tile)];
+        if best.is_none_or(|best| (steps, tile) < best) {
+            *best = Some((steps, tile));
+        }
+    }
+    let mut draw = |from: (i32, i32), color: Color| {
+        let points = paths.0.path(from).into_iter().map(|(x, y)| Vec2::from(map::get_world(x, y)));
+        gizmos.linestrip_2d(points, color);
+        let (x, y) = map::get_world(from.0, from.1);
+        gizmos.circle_2d(Vec2::new(x, y), TILE_SIZE / 3., color);
+    };
+    for (_, tile) in closest.into_iter().flatten() {
+        draw(tile, Color::RED);
+    }
+    for transform in enemies.iter() {
+        draw(map::get_tile(transform.translation.x, transform.translation.y), Color::ORANGE);
+    }
+}
+
+/// Text for the cursor and stats overlays, in the top right. Spawned as needed,
+/// since leaving a screen clears its UI.
+#[allow(clippy::too_many_arguments)]
+fn show_panel(
+    mut commands: Commands,
+    overlays: Res<DebugOverlays>,
+    game_assets: Res<GameAssets>,
+    mouse: Res<MyWorldCoords>,
+    tile_map: Res<TileMap>,
+    diagnostics: Res<DiagnosticsStore>,
+    mut gizmos: Gizmos,
+    mut panel: Query<(Entity, &mut Text), With<DebugPanel>>,
+) {
+    let mut lines = Vec::new();
+    if overlays.cursor {
+        let (x, y) = map::get_tile(mouse.0.x, mouse.0.y);
+        let (world_x, world_y) = map::get_world(x, y);
+        gizmos.rect_2d(Vec2::new(world_x, world_y), 0., Vec2::splat(TILE_SIZE), Color::WHITE);
+        match tile_map.get(x, y) {
+            Some(tile) => lines.push(format!("Tile {}, {}  {:?} {:?}", x, y, tile.state, tile.kind)),
+            None => lines.push(format!("Tile {}, {}  off the map", x, y)),
+        }
+    }
+    if overlays.stats {
+        let value = |path: &DiagnosticPath| diagnostics.get(path).and_then(|diagnostic| diagnostic.smoothed()).unwrap_or(0.);
+        lines.push(format!("{:.0} fps", value(&FrameTimeDiagnosticsPlugin::FPS)));
+        lines.push(format!("{:.0} entities", value(&EntityCountDiagnosticsPlugin::ENTITY_COUNT)));
+    }
+
+    if lines.is_empty() {
+        for (entity, _) in panel.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    }
+    let value = lines.join("\n");
+    if let Ok((_, mut text)) = panel.get_single_mut() {
+        text.sections[0].value = value;
+        return;
+    }
+    commands.spawn((DebugPanel, TextBundle::from_section(
+        value,
+        TextStyle {
+            font: game_assets.ui_font.clone(),
+            font_size: 18.0,
+            color: Color::WHITE,
+        },
+    ).with_style(Style {
+        position_type: PositionType::Absolute,
+        top: Val::Px(48.0),
+        right: Val::Px(10.0),
+        ..default()
+    })));
+}
@@ -0,0 +1,218 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_hanabi::prelude::*;
+
+/// Named particle effects gameplay can fire
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum EffectKind {
+    TillingDust,
+    PlantingSparkle,
+    HarvestBurst,
+    EnemyHit,
+    EnemyDeath,
+    CoreDamage,
+    CoreDestruction,
+    /// Keeps going for as long as the entity it's attached to lives
+    TorchEmbers,
+}
+
+impl EffectKind {
+    pub const ALL: [EffectKind; 8] = [
+        EffectKind::TillingDust,
+        EffectKind::PlantingSparkle,
+        EffectKind::HarvestBurst,
+        EffectKind::EnemyHit,
+        EffectKind::EnemyDeath,
+        EffectKind::CoreDamage,
+        EffectKind::CoreDestruction,
+        EffectKind::TorchEmbers,
+    ];
+
+    /// How the effect looks, built once into an asset at load
+    fn recipe(&self) -> EffectRecipe {
+        match self {
+            EffectKind::TillingDust => EffectRecipe {
+                count: 16., lifetime: 0.6, radius: 8., speed: (10., 40.), gravity: -60., size: 3.,
+                colors: (Vec4::new(0.6, 0.35, 0.2, 1.), Vec4::new(0.5, 0.3, 0.2, 0.)), looping: false,
+            },
+            EffectKind::PlantingSparkle => EffectRecipe {
+                count: 12., lifetime: 0.8, radius: 6., speed: (15., 30.), gravity: 20., size: 2.,
+                colors: (Vec4::new(1.5, 2., 1., 1.), Vec4::new(0.6, 1., 0.4, 0.)), looping: false,
+            },
+            EffectKind::HarvestBurst => EffectRecipe {
+                count: 32., lifetime: 0.9, radius: 4., speed: (60., 120.), gravity: -120., size: 3.,
+                colors: (Vec4::new(2., 1.8, 0.6, 1.), Vec4::new(1., 0.6, 0.2, 0.)), looping: false,
+            },
+            EffectKind::EnemyHit => EffectRecipe {
+                count: 10., lifetime: 0.35, radius: 4., speed: (40., 90.), gravity: -80., size: 2.,
+                colors: (Vec4::new(2.5, 2.5, 1.5, 1.), Vec4::new(0.8, 0.3, 0.3, 0.)), looping: false,
+            },
+            EffectKind::EnemyDeath => EffectRecipe {
+                count: 48., lifetime: 0.9, radius: 10., speed: (30., 110.), gravity: -60., size: 3.,
+                colors: (Vec4::new(0.8, 1.4, 0.5, 1.), Vec4::new(0.2, 0.15, 0.2, 0.)), looping: false,
+            },
+            EffectKind::CoreDamage => EffectRecipe {
+                count: 20., lifetime: 0.5, radius: 12., speed: (40., 80.), gravity: 0., size: 3.,
+                colors: (Vec4::new(0.6, 1.2, 3., 1.), Vec4::new(0.2, 0.4, 1., 0.)), looping: false,
+            },
+            EffectKind::CoreDestruction => EffectRecipe {
+                count: 200., lifetime: 2., radius: 16., speed: (80., 240.), gravity: -30., size: 5.,
+                colors: (Vec4::new(3., 3., 4., 1.), Vec4::new(0.3, 0.5, 1., 0.)), looping: false,
+            },
+            EffectKind::TorchEmbers => EffectRecipe {
+                count: 6., lifetime: 1.2, radius: 3., speed: (5., 15.), gravity: 25., size: 2.,
+                colors: (Vec4::new(3., 1.5, 0.4, 1.), Vec4::new(1., 0.3, 0.1, 0.)), looping: true,
+            },
+        }
+    }
+}
+
+/// Knobs for a simple radial particle effect
+struct EffectRecipe {
+    /// Particles per burst, or per second when looping
+    count: f32,
+    lifetime: f32,
+    /// Spawn circle radius
+    radius: f32,
+    /// Outward speed range
+    speed: (f32, f32),
+    /// Upward acceleration, negative falls
+    gravity: f32,
+    size: f32,
+    /// Color at birth and at death
+    colors: (Vec4, Vec4),
+    looping: bool,
+}
+
+impl EffectRecipe {
+    fn build(&self, name: &str) -> EffectAsset {
+        let writer = ExprWriter::new();
+
+        let init_pos = SetPositionCircleModifier {
+            center: writer.lit(Vec3::ZERO).expr(),
+            axis: writer.lit(Vec3::Z).expr(),
+            radius: writer.lit(self.radius).expr(),
+            dimension: ShapeDimension::Volume,
+        };
+        let init_vel = SetVelocityCircleModifier {
+            center: writer.lit(Vec3::ZERO).expr(),
+            axis: writer.lit(Vec3::Z).expr(),
+            speed: (writer.lit(self.speed.0) + writer.rand(ScalarType::Float) * writer.lit(self.speed.1 - self.speed.0)).expr(),
+        };
+        let init_age = SetAttributeModifier::new(Attribute::AGE, writer.lit(0.).expr());
+        let init_lifetime = SetAttributeModifier::new(Attribute::LIFETIME, writer.lit(self.lifetime).expr());
+        let accel = AccelModifier::new(writer.lit(Vec3::new(0., self.gravity, 0.)).expr());
+
+        let mut gradient = Gradient::new();
+        gradient.add_key(0.0, self.colors.0);
+        gradient.add_key(1.0, self.colors.1);
+
+        let spawner = if self.looping {
+            Spawner::rate(self.count.into())
+        } else {
+            Spawner::once(self.count.into(), true)
+        };
+
+        EffectAsset::new(1024, spawner, writer.finish())
+            .with_name(name)
+            .init(init_pos)
+            .init(init_vel)
+            .init(init_age)
+            .init(init_lifetime)
+            .update(accel)
+            .render(SizeOverLifetimeModifier {
+                gradient: Gradient::constant(Vec2::splat(self.size)),
+                screen_space_size: false,
+            })
+            .render(ColorOverLifetimeModifier { gradient })
+    }
+}
+
+/// Loaded effect assets by name. Missing when particles can't run (no GPU),
+/// in which case effects are quietly skipped.
+#[derive(Resource)]
+pub struct ParticleEffects {
+    handles: HashMap<EffectKind, Handle<EffectAsset>>,
+}
+
+impl ParticleEffects {
+    /// Particle bundle for an effect at a spot in the world
+    pub fn bundle(&self, effect: EffectKind, position: Vec2) -> Option<ParticleEffectBundle> {
+        let handle = self.handles.get(&effect)?;
+        Some(ParticleEffectBundle {
+            effect: ParticleEffect::new(handle.clone()).with_z_layer_2d(Some(10.)),
+            transform: Transform::from_xyz(position.x, position.y, 10.),
+            ..default()
+        })
+    }
+}
+
+/// Fire a one shot effect at a world position
+#[derive(Event, Clone, Copy, Debug)]
+pub struct SpawnEffectEvent {
+    pub effect: EffectKind,
+    pub position: Vec2,
+}
+
+/// One shot effect that cleans itself up once its particles are gone
+#[derive(Component)]
+pub struct EffectLifetime(pub Timer);
+
+/// Entity that gets torch embers rising off it
+#[derive(Component)]
+pub struct Embers;
+
+pub fn setup_effects(mut commands: Commands, effects: Option<ResMut<Assets<EffectAsset>>>) {
+    let Some(mut effects) = effects else {
+        warn!("Particle effects are unavailable, skipping them");
+        return;
+    };
+    let handles = EffectKind::ALL.iter()
+        .map(|kind| (*kind, effects.add(kind.recipe().build(&format!("{:?}", kind)))))
+        .collect();
+    commands.insert_resource(ParticleEffects { handles });
+}
+
+pub fn spawn_effects(
+    mut commands: Commands,
+    mut ev_effects: EventReader<SpawnEffectEvent>,
+    effects: Option<Res<ParticleEffects>>,
+) {
+    let Some(effects) = effects else {
+        ev_effects.clear();
+        return;
+    };
+    for ev in ev_effects.read() {
+        let Some(bundle) = effects.bundle(ev.effect, ev.position) else { continue; };
+        // Give the last particles time to fade before despawning
+        let lifetime = ev.effect.recipe().lifetime + 0.5;
+        commands.spawn((bundle, EffectLifetime(Timer::from_seconds(lifetime, TimerMode::Once))));
+    }
+}
+
+pub fn despawn_finished_effects(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut EffectLifetime)>,
+) {
+    for (entity, mut lifetime) in query.iter_mut() {
+        if lifetime.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Start embers on anything newly marked for them
+pub fn attach_embers(
+    mut commands: Commands,
+    effects: Option<Res<ParticleEffects>>,
+    query: Query<Entity, Added<Embers>>,
+) {
+    let Some(effects) = effects else { return; };
+    for entity in query.iter() {
+        // Emitter is a child, so it sits just above the torch flame
+        let Some(bundle) = effects.bundle(EffectKind::TorchEmbers, Vec2::new(0., 8.)) else { continue; };
+        commands.entity(entity).with_children(|parent| {
+            parent.spawn(bundle);
+        });
+    }
+}
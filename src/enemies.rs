@@ -0,0 +1,414 @@
+//! Enemies come in over the map edges each night and make for the core. They keep
+//! to the dark where they can, and crawl when a light is on them.
+
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{
+    building::{Building, Buildings},
+    day_night::{DayCount, DayPhase, PhaseChangedEvent},
+    effects::{EffectKind, SpawnEffectEvent},
+    lighting::{self, LightGlow, LightingPhase, Lit},
+    loading::GameAssets,
+    map::{self, MapSize, TileMap},
+    pathfinding::{self, FlowField},
+    player::SwingEvent,
+    run::RunRng,
+    spriteanims::{self, Action, AnimationSheet, Facing, Moving, SpriteAnimator},
+    the_core::{self, CoreHealth, GodMode},
+    tools::Tools,
+    walls,
+};
+
+/// Seconds between enemies coming in during a wave
+const SPAWN_GAP: f32 = 2.5;
+/// Enemies in the first night's wave, every night after brings one more
+const FIRST_WAVE: u32 = 3;
+/// Pixels from the player a swing of the hoe reaches, all the way round
+const SWING_REACH: f32 = 28.;
+/// How hard light slows enemies down, their speed is divided by 1 + this times the glow
+const LIGHT_SLOW: f32 = 1.5;
+
+/// The kinds of enemy there are
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum EnemyKind {
+    Crawler,
+    Brute,
+}
+
+impl EnemyKind {
+    pub const ALL: [EnemyKind; 2] = [EnemyKind::Crawler, EnemyKind::Brute];
+
+    /// Name of its sheet in `GameAssets::enemy_sheets`
+    pub fn name(&self) -> &'static str {
+        match self {
+            EnemyKind::Crawler => "crawler",
+            EnemyKind::Brute => "brute",
+        }
+    }
+
+    pub fn max_health(&self) -> f32 {
+        match self {
+            EnemyKind::Crawler => 20.,
+            EnemyKind::Brute => 60.,
+        }
+    }
+
+    /// Pixels a second, out in the dark
+    pub fn speed(&self) -> f32 {
+        match self {
+            EnemyKind::Crawler => 72.,
+            EnemyKind::Brute => 45.,
+        }
+    }
+
+    /// Core health it takes when it gets there
+    pub fn damage(&self) -> f32 {
+        match self {
+            EnemyKind::Crawler => 5.,
+            EnemyKind::Brute => 15.,
+        }
+    }
+}
+
+/// Something that wants the core gone. Only changes when it's spawned or hurt.
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+pub struct Enemy {
+    pub kind: EnemyKind,
+    pub health: f32,
+}
+
+/// Where an enemy is headed next
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+pub struct Walker {
+    /// Tile it's walking to the middle of
+    pub target: (i32, i32),
+    /// How far it went last tick, zero when it's standing still
+    pub heading: Vec2,
+}
+
+#[derive(Bundle)]
+pub struct EnemyBundle {
+    enemy: Enemy,
+    walker: Walker,
+    transform: Transform,
+}
+
+impl EnemyBundle {
+    /// A fresh enemy standing in the middle of a tile
+    pub fn new(kind: EnemyKind, tile: (i32, i32)) -> EnemyBundle {
+        let (x, y) = map::get_world(tile.0, tile.1);
+        EnemyBundle {
+            enemy: Enemy { kind, health: kind.max_health() },
+            walker: Walker { target: tile, heading: Vec2::ZERO },
+            transform: Transform::from_xyz(x, y, 1.5),
+        }
+    }
+}
+
+/// How tonight's wave is coming along
+#[derive(Resource, Default)]
+pub struct Waves {
+    /// Enemies still to come in tonight
+    pub remaining: u32,
+    /// Seconds since the last one came in
+    elapsed: f32,
+}
+
+/// Enemies in the wave that comes on a day's night
+pub fn wave_size(day: u32) -> u32 {
+    FIRST_WAVE + day.saturating_sub(1)
+}
+
+/// The way to the core from every tile, kept up to date as walls go up and come down
+#[derive(Resource, Default)]
+pub struct CorePaths(pub FlowField);
+
+pub fn update_paths(
+    buildings: Res<Buildings>,
+    placed: Query<&Building>,
+    tile_map: Res<TileMap>,
+    map_size: Res<MapSize>,
+    mut paths: ResMut<CorePaths>,
+) {
+    if !buildings.is_changed() {
+        return;
+    }
+    let core = the_core::core_tile(&map_size);
+    paths.0 = FlowField::new(tile_map.width(), tile_map.height(), core, |tile| walls::is_wall(&buildings, &placed, tile));
+}
+
+/// Nightfall starts a wave that trickles in from the entry points, bigger every night
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_waves(
+    mut commands: Commands,
+    mut ev_phase: EventReader<PhaseChangedEvent>,
+    time: Res<Time>,
+    day: Res<DayCount>,
+    mut waves: ResMut<Waves>,
+    paths: Res<CorePaths>,
+    tile_map: Res<TileMap>,
+    mut rng: ResMut<RunRng>,
+    enemies: Query<Entity, With<Enemy>>,
+) {
+    for ev in ev_phase.read() {
+        match ev.to {
+            DayPhase::Night => {
+                *waves = Waves { remaining: wave_size(ev.day), elapsed: SPAWN_GAP };
+            }
+            // Whatever's still out when the sun comes up slinks off
+            DayPhase::Dawn => {
+                waves.remaining = 0;
+                for entity in enemies.iter() {
+                    commands.entity(entity).despawn_recursive();
+                }
+            }
+            _ => {}
+        }
+    }
+    if waves.remaining == 0 {
+        return;
+    }
+    waves.elapsed += time.delta_seconds();
+    if waves.elapsed < SPAWN_GAP {
+        return;
+    }
+    waves.elapsed -= SPAWN_GAP;
+
+    // Only from where the core can be got to
+    let entries: Vec<(i32, i32)> = pathfinding::entries(tile_map.width(), tile_map.height())
+        .filter(|tile| paths.0.steps(*tile).is_some())
+        .collect();
+    if entries.is_empty() {
+        return;
+    }
+    let tile = entries[rng.0.gen_range(0..entries.len())];
+    // Brutes start turning up after the first night
+    let kind = if day.0 > 1 && rng.0.gen_ratio(1, 4) { EnemyKind::Brute } else { EnemyKind::Crawler };
+    commands.spawn(EnemyBundle::new(kind, tile));
+    waves.remaining -= 1;
+}
+
+/// Hoe swings hurt every enemy in reach, harder the better the hoe
+pub fn hit_enemies(
+    mut commands: Commands,
+    mut ev_swing: EventReader<SwingEvent>,
+    tools: Res<Tools>,
+    mut enemies: Query<(Entity, &mut Enemy, &Transform)>,
+    mut ev_effects: EventWriter<SpawnEffectEvent>,
+) {
+    for SwingEvent(position) in ev_swing.read() {
+        for (entity, mut enemy, transform) in enemies.iter_mut() {
+            let at = transform.translation.truncate();
+            // Already going down from an earlier swing this tick
+            if enemy.health <= 0. || at.distance(*position) > SWING_REACH {
+                continue;
+            }
+            enemy.health -= tools.hoe.damage();
+            let effect = if enemy.health <= 0. { EffectKind::EnemyDeath } else { EffectKind::EnemyHit };
+            ev_effects.send(SpawnEffectEvent { effect, position: at });
+            if enemy.health <= 0. {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+/// Walk every enemy a tile at a time towards the core, slower the more light is on them.
+/// Any that get there hurt the core and are gone.
+#[allow(clippy::too_many_arguments)]
+pub fn move_enemies(
+    mut commands: Commands,
+    time: Res<Time>,
+    paths: Res<CorePaths>,
+    light_glow: Res<LightGlow>,
+    phase: Res<LightingPhase>,
+    map_size: Res<MapSize>,
+    god_mode: Res<GodMode>,
+    mut core: ResMut<CoreHealth>,
+    mut enemies: Query<(Entity, &Enemy, &mut Walker, &mut Transform)>,
+) {
+    let core_tile = the_core::core_tile(&map_size);
+    for (entity, enemy, mut walker, mut transform) in enemies.iter_mut() {
+        let here = map::get_tile(transform.translation.x, transform.translation.y);
+        let glow = lighting::glow_at(&light_glow, &phase, here.0, here.1);
+        let mut step = enemy.kind.speed() / (1. + LIGHT_SLOW * glow) * time.delta_seconds();
+        let start = transform.translation;
+
+        let mut arrived = false;
+        while step > 0. {
+            let (x, y) = map::get_world(walker.target.0, walker.target.1);
+            let to_target = Vec3::new(x, y, start.z) - transform.translation;
+            if to_target.length() > step {
+                transform.translation += to_target.normalize() * step;
+                break;
+            }
+            transform.translation = Vec3::new(x, y, start.z);
+            step -= to_target.length();
+            if walker.target == core_tile {
+                arrived = true;
+                break;
+            }
+            // Cut off from the core, so it waits where it is
+            let Some(next) = paths.0.next(walker.target) else { break; };
+            walker.target = next;
+        }
+        walker.heading = (transform.translation - start).truncate();
+
+        if arrived {
+            if !god_mode.0 {
+                core.0 = (core.0 - enemy.kind.damage()).max(0.);
+            }
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Give newly spawned enemies their sprite
+pub fn show_enemies(
+    mut commands: Commands,
+    game_assets: Res<GameAssets>,
+    sheets: Res<Assets<AnimationSheet>>,
+    enemies: Query<(Entity, &Enemy, &Transform), Added<Enemy>>,
+) {
+    for (entity, enemy, transform) in enemies.iter() {
+        let Some(handle) = game_assets.enemy_sheets.get(enemy.kind.name()) else { continue; };
+        let Some(sheet) = sheets.get(handle) else { continue; };
+        commands.entity(entity).insert((spriteanims::HumanAnimator::new(handle.clone(), sheet, transform.translation), Lit));
+    }
+}
+
+/// Enemies flinch when they're hit, which is the only time they change after they're spawned
+pub fn hurt_enemies(mut enemies: Query<(Ref<Enemy>, &Facing, &mut SpriteAnimator)>) {
+    for (enemy, facing, mut animator) in enemies.iter_mut() {
+        if enemy.is_changed() && !enemy.is_added() {
+            animator.restart(spriteanims::clip_name(Action::Hurt, *facing));
+        }
+    }
+}
+
+/// Face enemies the way they're walking
+pub fn face_enemies(mut enemies: Query<(&Walker, &mut Facing, &mut Moving)>) {
+    for (walker, mut facing, mut moving) in enemies.iter_mut() {
+        let heading = walker.heading;
+        moving.0 = heading != Vec2::ZERO;
+        if !moving.0 {
+            continue;
+        }
+        let turned = match (heading.x.abs() > heading.y.abs(), heading.x > 0., heading.y > 0.) {
+            (true, true, _) => Facing::Right,
+            (true, false, _) => Facing::Left,
+            (false, _, true) => Facing::Up,
+            (false, _, false) => Facing::Down,
+        };
+        if *facing != turned {
+            *facing = turned;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::event::ManualEventReader;
+    use crate::{building::Buildable, game::Stockpile, player::{ClickEvent, PlayerTool}, sim, the_core::CORE_MAX_HEALTH, tools::HoeTier};
+
+    fn position(app: &App, entity: Entity) -> Vec2 {
+        app.world.get::<Transform>(entity).unwrap().translation.truncate()
+    }
+
+    #[test]
+    fn enemies_crawl_in_the_light() {
+        let mut app = sim::fresh_run();
+        app.world.resource_mut::<Stockpile>().0 = 100;
+        // Two enemies the same way off the core, one of them under a lamp
+        let (core_x, core_y) = the_core::core_tile(app.world.resource::<MapSize>());
+        let lit = (core_x - 20, core_y + 30);
+        let dark = (core_x + 20, core_y + 30);
+        app.world.send_event(ClickEvent((lit.0, lit.1 + 1), PlayerTool::Build(Buildable::Lamp)));
+        app.update();
+        let lit_enemy = app.world.spawn(EnemyBundle::new(EnemyKind::Crawler, lit)).id();
+        let dark_enemy = app.world.spawn(EnemyBundle::new(EnemyKind::Crawler, dark)).id();
+
+        for _ in 0..15 {
+            app.update();
+        }
+        let lit_moved = position(&app, lit_enemy).distance(Vec2::from(map::get_world(lit.0, lit.1)));
+        let dark_moved = position(&app, dark_enemy).distance(Vec2::from(map::get_world(dark.0, dark.1)));
+        assert!(lit_moved > 0.);
+        assert!(lit_moved < dark_moved * 0.6, "{} in the light, {} in the dark", lit_moved, dark_moved);
+    }
+
+    #[test]
+    fn enemy_at_the_core_hurts_it() {
+        let mut app = sim::fresh_run();
+        let core = the_core::core_tile(app.world.resource::<MapSize>());
+        let enemy = app.world.spawn(EnemyBundle::new(EnemyKind::Brute, (core.0, core.1 + 3))).id();
+
+        for _ in 0..600 {
+            app.update();
+        }
+        assert!(app.world.get_entity(enemy).is_none());
+        assert_eq!(app.world.resource::<CoreHealth>().0, CORE_MAX_HEALTH - EnemyKind::Brute.damage());
+    }
+
+    #[test]
+    fn better_hoes_hit_harder() {
+        let mut app = sim::fresh_run();
+        // Out past the homestead's lights, where the enemy keeps moving
+        let tile = (5, 30);
+        let enemy = app.world.spawn(EnemyBundle::new(EnemyKind::Crawler, tile)).id();
+        let swing = |app: &mut App| {
+            let position = position(app, enemy);
+            app.world.send_event(SwingEvent(position));
+            app.update();
+        };
+
+        swing(&mut app);
+        assert_eq!(app.world.get::<Enemy>(enemy).unwrap().health, EnemyKind::Crawler.max_health() - HoeTier::Hoe1.damage());
+        // Out of reach
+        app.world.send_event(SwingEvent(position(&app, enemy) + Vec2::new(SWING_REACH + 10., 0.)));
+        app.update();
+        assert_eq!(app.world.get::<Enemy>(enemy).unwrap().health, EnemyKind::Crawler.max_health() - HoeTier::Hoe1.damage());
+
+        app.world.resource_mut::<Tools>().hoe = HoeTier::Hoe3;
+        swing(&mut app);
+        assert!(app.world.get_entity(enemy).is_none());
+    }
+
+    #[test]
+    fn swings_burst_on_a_hit_and_a_kill() {
+        let mut app = sim::fresh_run();
+        let enemy = app.world.spawn(EnemyBundle::new(EnemyKind::Crawler, (5, 30))).id();
+        let mut reader = ManualEventReader::<SpawnEffectEvent>::default();
+        let mut effects = Vec::new();
+        while app.world.get_entity(enemy).is_some() {
+            let position = position(&app, enemy);
+            app.world.send_event(SwingEvent(position));
+            app.update();
+            effects.extend(reader.read(app.world.resource::<Events<SpawnEffectEvent>>()).map(|ev| ev.effect));
+        }
+        assert_eq!(effects, vec![EffectKind::EnemyHit, EffectKind::EnemyDeath]);
+    }
+
+    #[test]
+    fn night_brings_a_wave_and_dawn_clears_it() {
+        let mut app = sim::fresh_run();
+        let count = |app: &mut App| app.world.query::<&Enemy>().iter(&app.world).count();
+        app.world.send_event(PhaseChangedEvent { from: DayPhase::Dusk, to: DayPhase::Night, day: 1 });
+        app.update();
+        assert_eq!(count(&mut app), 1);
+        assert_eq!(app.world.resource::<Waves>().remaining, wave_size(1) - 1);
+
+        // The rest follow one at a time
+        let ticks = (SPAWN_GAP * wave_size(1) as f32 * 30.) as usize;
+        for _ in 0..ticks {
+            app.update();
+        }
+        assert_eq!(app.world.resource::<Waves>().remaining, 0);
+
+        app.world.send_event(PhaseChangedEvent { from: DayPhase::Night, to: DayPhase::Dawn, day: 2 });
+        app.update();
+        assert_eq!(count(&mut app), 0);
+    }
+}
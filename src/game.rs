@@ -1,91 +1,275 @@
-use std::time::Duration;
+use bevy::{prelude::*, window::PrimaryWindow};
+#[cfg(feature = "audio")]
+use bevy_kira_audio::{Audio, AudioChannel};
+use crate::{bases, building, camera::{self, MainCamera}, chunk, crops, day_night, effects, enemies, loading::{GameAssets, TileAtlas}, lighting, map::{self, MapSize}, player, replay, run::{Run, RunRng}, the_core, sfx, tools, undo, walls, weather};
+#[cfg(feature = "audio")]
+use crate::music_player;
 
-use bevy::{audio::AudioPlugin, core_pipeline::{bloom::BloomSettings, tonemapping::Tonemapping}, ecs::query, prelude::*, window::PrimaryWindow};
-use bevy::render::*;
-use crate::{map::{self, Position}, player, the_core, music_player};
-
-use super::{AppState, RESOLUTION_X, RESOLUTION_Y};
+use super::AppState;
 
 
 /// We will store the world position of the mouse cursor here.
 #[derive(Resource, Default)]
 pub struct MyWorldCoords(pub Vec2);
 
-/// Used to help identify our main camera
-#[derive(Component)]
-struct MainCamera;
+/// Resources the player starts a run with
+const STARTING_RESOURCES: u32 = 6;
+
+/// Resources the player has to spend on buildings
+#[derive(Resource)]
+pub struct Stockpile(pub u32);
+
+impl Default for Stockpile {
+    fn default() -> Self {
+        Stockpile(STARTING_RESOURCES)
+    }
+}
+
+/// Fixed ticks since the run started. Replays line inputs up against it.
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Tick(pub u32);
+
+/// Order of each fixed tick: gather inputs, note them down, then play out the rules.
+/// Everything that changes the game happens in `Simulate`, so a tick plays out the
+/// same way given the same inputs.
+#[derive(SystemSet, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum TickSet {
+    Input,
+    Record,
+    Simulate,
+}
 
 
 
 pub fn build_plugin(app: &mut App){
+    build_simulation(app);
+    #[cfg(feature = "audio")]
+    build_audio(app);
+
     app
-    .add_event::<player::ClickEvent>()
     .add_systems(OnEnter(AppState::Game), (
-        setup,
+        setup_view,
         player::setup,
-        the_core::setup,
+        weather::setup,
     ))
     .init_resource::<MyWorldCoords>()
+    .init_resource::<camera::CameraSettings>()
+    .add_systems(OnExit(AppState::Loading), (chunk::setup_material, weather::setup_effects, effects::setup_effects))
 
-    // Setup music handler
+    // Gameplay particles
     .add_systems(Update, (
-        music_player::instance_control
+        effects::spawn_effects,
+        effects::despawn_finished_effects,
+        effects::attach_embers,
     ).run_if(in_state(AppState::Game)))
 
-    .add_systems(Startup, (
-        music_player::start_day_music,
-        music_player::start_night_music
-    ))
-
-    // Add day night cycle events
-    .add_event::<DawnStartEvent>()
-    .add_event::<DayStartEvent>()
-    .add_event::<DuskStartEvent>()
-    .add_event::<NightStartEvent>()
+    // Sound effects get asked for in every state so menu buttons click too
+    .init_resource::<sfx::SfxSettings>()
+    .add_systems(Update, sfx::button_sfx)
+    .add_systems(Update, (sfx::phase_sfx, the_core::core_hurt.run_if(resource_exists::<the_core::CoreHealth>)).run_if(in_state(AppState::Game)))
 
-    .insert_resource(DayNightCycle::new(DAY_DURATION))
+    // Day night cycle
     .add_systems(Update, (
-        update_day_night_cycle,
-        handle_day_night_events,
+        day_night::time_control_keys.run_if(not(resource_exists::<replay::ReplayPlayback>)),
+        day_night::handle_day_night_events,
+        lighting::light_sprites,
     ).run_if(in_state(AppState::Game)))
-    
+
+    // Weather shows itself
     .add_systems(Update, (
-        my_cursor_system
+        weather::show_weather,
+        weather::follow_camera.after(camera::camera_follow),
     ).run_if(in_state(AppState::Game)))
 
+    // Camera follows the player, then the cursor is resolved against the moved camera
+    .add_systems(Update, (
+        camera::camera_zoom,
+        camera::camera_follow,
+        my_cursor_system,
+    ).chain().run_if(in_state(AppState::Game)))
+
+    // Tile edits and lighting land in the chunk meshes once per frame
+    .add_systems(PostUpdate, (
+        chunk::rebuild_dirty_chunks,
+        chunk::refresh_chunk_lighting,
+    ).chain().run_if(in_state(AppState::Game)))
+
+    // A replay moves the player itself, and Space pauses the viewer rather than swinging the hoe
+    .add_systems(FixedUpdate, player::player_input
+        .in_set(TickSet::Input)
+        .run_if(in_state(AppState::Game).and_then(not(resource_exists::<replay::ReplayPlayback>))))
     .add_systems(FixedUpdate, (
-        player::player_input,
         player::render_tile_highlight,
         player::hoe_swing,
-        player::mouse_tile_select,
-        player::react_to_mouse_event,
         the_core::core_update,
-
     ).run_if(in_state(AppState::Game)))
-    
-    .add_systems(OnExit(AppState::Game), cleanup);
+    .add_systems(Update, tools::update_hoe.run_if(in_state(AppState::Game)))
+    .add_systems(Update, walls::connect_walls.run_if(in_state(AppState::Game)))
+    .add_systems(Update, (enemies::show_enemies, enemies::hurt_enemies, enemies::face_enemies).run_if(in_state(AppState::Game)))
+    .add_systems(Update, bases::show_damage.run_if(in_state(AppState::Game)))
+    .add_systems(Update, bases::recall.run_if(in_state(AppState::Game).and_then(not(resource_exists::<replay::ReplayPlayback>))))
+    // Clicks and drags queue up tiles for the player to work. A replay does the clicking instead.
+    .init_resource::<player::WorkQueue>()
+    .init_resource::<player::DragSelect>()
+    .add_systems(Update, (player::select_tiles, player::preview_selection)
+        .chain()
+        .after(my_cursor_system)
+        .run_if(in_state(AppState::Game).and_then(not(resource_exists::<replay::ReplayPlayback>))))
+    .add_systems(FixedUpdate, player::work_queue
+        .in_set(TickSet::Input)
+        .run_if(in_state(AppState::Game).and_then(not(resource_exists::<replay::ReplayPlayback>))))
+    .add_systems(Update, undo::undo_keys.run_if(in_state(AppState::Game).and_then(not(resource_exists::<replay::ReplayPlayback>))))
+
+    // Replays save as the run goes, and get their own playback controls
+    .add_systems(Update, replay::save_replay_each_day.run_if(in_state(AppState::Game).and_then(resource_exists::<replay::ReplayRecorder>)))
+    .add_systems(OnExit(AppState::Game), replay::save_replay.before(replay::stop_replay).run_if(resource_exists::<replay::ReplayRecorder>))
+    .add_systems(OnEnter(AppState::Game), replay::setup_viewer.run_if(resource_exists::<replay::ReplayPlayback>))
+    .add_systems(PreUpdate, replay::viewer_controls
+        .after(bevy::input::InputSystem)
+        .run_if(in_state(AppState::Game).and_then(resource_exists::<replay::ReplayPlayback>)))
+    .add_systems(Update, replay::update_viewer.run_if(in_state(AppState::Game).and_then(resource_exists::<replay::ReplayPlayback>)));
 }
 
-fn setup(mut commands: Commands, assets: Res<AssetServer>) {
-    
-    commands.spawn(( //Camera with bloom settings enabled
-        Camera2dBundle {
-            camera: Camera {
-                hdr: true,
-                ..default()
-            },
-            tonemapping: Tonemapping::TonyMcMapface,
-            transform: Transform::from_xyz((RESOLUTION_X / 2.) - 16.0, (RESOLUTION_Y / 2.) - 16.0 , 0.),
-            ..default()
-        },
-        BloomSettings{
-            intensity: 0.1,
-            ..default()
-        },
-        MainCamera
+/// Music and sound effects, for builds with the `audio` feature
+#[cfg(feature = "audio")]
+fn build_audio(app: &mut App) {
+    app
+    // Music follows the app state and the intensity of the game. Everything audio
+    // is skipped when there's no audio plugin, like in a headless test harness.
+    .add_event::<music_player::StingerEvent>()
+    .init_resource::<music_player::GameIntensity>()
+    .add_systems(OnEnter(AppState::Menu), music_player::start_menu_music.run_if(resource_exists::<Audio>))
+    .add_systems(OnEnter(AppState::Game), (
+        music_player::stop_menu_music,
+        // Picks the track for the phase the fresh run starts in
+        music_player::start_music.after(setup),
+    ).run_if(resource_exists::<Audio>))
+    .add_systems(OnExit(AppState::Game), music_player::stop_music.run_if(resource_exists::<Audio>))
+    .add_systems(Update, (
+        (
+            (the_core::core_threat, music_player::queue_stingers).run_if(resource_exists::<the_core::CoreHealth>),
+            music_player::enemy_approach,
+            music_player::update_intensity,
+            music_player::direct_music.run_if(resource_exists::<music_player::MusicDirector>),
+        ).chain(),
+        music_player::weather_music.run_if(resource_exists::<music_player::MusicDirector>),
+    ).run_if(in_state(AppState::Game)))
+
+    .init_resource::<sfx::SfxCooldowns>()
+    .add_systems(Update, (
+        sfx::apply_sfx_settings.run_if(resource_exists::<AudioChannel<sfx::SfxChannel>>),
+        sfx::play_sfx.after(sfx::button_sfx).run_if(resource_exists::<AudioChannel<sfx::SfxChannel>>.and_then(resource_exists::<GameAssets>)),
     ));
+}
+
+/// The rules of the game on their own, with no window, renderer, audio or input.
+/// `build_plugin` adds everything you see and hear on top, and `sim` runs this headless.
+pub fn build_simulation(app: &mut App) {
+    app
+    .add_event::<player::ClickEvent>()
+    .add_event::<player::SwingEvent>()
+    .add_event::<effects::SpawnEffectEvent>()
+    .add_event::<sfx::SfxEvent>()
+    .add_event::<replay::ConsoleLine>()
+    .add_systems(OnEnter(AppState::Game), (
+        setup,
+        the_core::setup,
+    ))
+    .init_resource::<Stockpile>()
+    .init_resource::<building::Buildings>()
+    .init_resource::<crops::Crops>()
+    .init_resource::<weather::Weather>()
+    .init_resource::<MapSize>()
+    .init_resource::<the_core::GodMode>()
+    .init_resource::<tools::Tools>()
+    .init_resource::<bases::Bases>()
+    .add_event::<tools::ToolShopEvent>()
+    .init_resource::<undo::EditHistory>()
+    .add_event::<undo::UndoEvent>()
+    .init_resource::<enemies::Waves>()
+    .init_resource::<enemies::CorePaths>()
+
+    // Day night cycle, with the light it casts
+    .add_event::<day_night::PhaseChangedEvent>()
+    .add_event::<day_night::TimeControlEvent>()
+    .init_resource::<day_night::DayNightSchedule>()
+    .init_resource::<day_night::DayNightCycle>()
+    .init_resource::<day_night::DayCount>()
+    .init_resource::<lighting::LightingPhase>()
+    .init_resource::<lighting::LightGlow>()
+
+    // Each fixed tick runs the rules in one fixed order, so runs can be replayed
+    .init_resource::<Tick>()
+    .configure_sets(FixedUpdate, (TickSet::Input, TickSet::Record, TickSet::Simulate).chain())
+    .add_systems(FixedUpdate, (
+        day_night::apply_time_controls,
+        day_night::update_day_night_cycle,
+        lighting::update_lighting,
+        // Weather rolls each dawn
+        weather::roll_daily_weather,
+        tools::tool_shop,
+        // Daytime edits can be taken back, so note what each tick's clicks change
+        undo::undo_edits,
+        undo::begin_edit,
+        player::react_to_mouse_event,
+        building::place_buildings,
+        undo::finish_edit,
+        // Mending a base can't be undone, so it happens once the edit is noted
+        bases::repair_bases,
+        crops::grow_crops,
+        weather::flare_damage,
+        bases::base_lights,
+        // Enemies find their way around whatever walls stand after all that
+        enemies::update_paths,
+        enemies::spawn_waves,
+        enemies::hit_enemies,
+        enemies::move_enemies,
+        advance_tick,
+    ).chain().in_set(TickSet::Simulate).run_if(in_state(AppState::Game)))
+
+    // Inputs get recorded, or played back from a replay, with a checksum each day
+    .add_systems(OnEnter(AppState::Game), replay::start_recording.after(setup))
+    .add_systems(FixedUpdate, (
+        replay::play_inputs.in_set(TickSet::Input).run_if(resource_exists::<replay::ReplayPlayback>),
+        replay::record_inputs.in_set(TickSet::Record).run_if(resource_exists::<replay::ReplayRecorder>),
+        replay::check_day.after(TickSet::Simulate),
+    ).run_if(in_state(AppState::Game)))
 
-    crate::map::draw_background(commands, assets);
+    .add_systems(OnExit(AppState::Game), (cleanup, day_night::reset_game_speed, replay::stop_replay));
+}
+
+/// Lay out a fresh farm for a new run
+fn setup(mut commands: Commands, game_assets: Res<GameAssets>, atlas: Res<TileAtlas>, map_size: Res<MapSize>, run: Res<Run>) {
+    commands.insert_resource(Stockpile::default());
+    commands.insert_resource(building::Buildings::default());
+    commands.insert_resource(crops::Crops::default());
+    commands.insert_resource(day_night::DayNightCycle::default());
+    commands.insert_resource(day_night::DayCount::default());
+    // Start the run on a calm day
+    commands.insert_resource(weather::Weather::default());
+    commands.insert_resource(RunRng::new(run.seed));
+    commands.insert_resource(tools::Tools::new(run.tool_wear));
+    commands.insert_resource(Tick::default());
+    commands.insert_resource(undo::EditHistory::default());
+    commands.insert_resource(bases::Bases::new(&map_size));
+    commands.insert_resource(enemies::Waves::default());
+
+    crate::map::draw_background(commands, &game_assets, &atlas, &map_size);
+}
+
+fn advance_tick(mut tick: ResMut<Tick>) {
+    tick.0 += 1;
+}
+
+/// Camera and render state for a new run
+fn setup_view(mut commands: Commands, map_size: Res<MapSize>) {
+    // Start the camera over the middle of the homestead
+    let (x, y) = map_size.home(map::HOME_WIDTH / 2, map::HOME_HEIGHT / 2);
+    let (x, y) = map::get_world(x, y);
+    camera::spawn_camera(&mut commands, Vec2::new(x, y));
+    commands.insert_resource(chunk::ChunkEntities::default());
+    #[cfg(feature = "audio")]
+    commands.insert_resource(music_player::GameIntensity::default());
 }
 
 
@@ -125,165 +309,3 @@ fn my_cursor_system(
         // eprintln!("World coords: {}/{}  - Tile: {}/{}", world_position.x, world_position.y, tile_cords.0, tile_cords.1);
     }
 }
-
-// Constants for day duration (in seconds)
-pub const DAY_DURATION: f32 = 60.0;
-const DAY_LIGHT_LEVEL: f32 = 1.5;
-const NIGHT_LIGHT_LEVEL: f32 = 0.6;
-
-// Resource to hold the day-night cycle timer
-#[derive(Resource)]
-pub struct DayNightCycle {
-    pub timer: Timer,
-    prev_time: f32,
-}
-
-#[derive(Event)]
-pub struct DawnStartEvent;
-
-#[derive(Event)]
-pub struct DayStartEvent;
-
-#[derive(Event)]
-pub struct DuskStartEvent;
-
-#[derive(Event)]
-pub struct NightStartEvent;
-
-impl DayNightCycle {
-    fn new(day_duration: f32) -> Self {
-        DayNightCycle {
-            timer: Timer::from_seconds(day_duration, TimerMode::Repeating),
-            prev_time: 0.,
-        }
-    }
-}
-
-fn handle_day_night_events(
-    mut ev_dawn: EventReader<DawnStartEvent>,
-    mut ev_day: EventReader<DayStartEvent>,
-    mut ev_dusk: EventReader<DuskStartEvent>,
-    mut ev_night: EventReader<NightStartEvent>,
-) {
-    for _ev in ev_dawn.read() {
-        eprintln!("Dawn");
-    }
-    for _ev in ev_day.read() {
-        eprintln!("Day");
-    }
-    for _ev in ev_dusk.read() {
-        eprintln!("Dusk");
-    }
-    for _ev in ev_night.read() {
-        eprintln!("Night");
-    }
-}
-
-fn update_day_night_cycle(
-    time: Res<Time>,
-    mut day_night_cycle: ResMut<DayNightCycle>,
-    mut query: Query<(&mut Sprite, &Position)>,
-    mut ev_dawn: EventWriter<DawnStartEvent>,
-    mut ev_day: EventWriter<DayStartEvent>,
-    mut ev_dusk: EventWriter<DuskStartEvent>,
-    mut ev_night: EventWriter<NightStartEvent>,
-) {
-
-    day_night_cycle.timer.tick(time.delta());
-
-    // Calculate the current time of day as a percentage (0.0 - 1.0)
-    let time_of_day = day_night_cycle.timer.elapsed_secs() / DAY_DURATION;
-
-    let day_state = match time_of_day {
-        t if t <= 0.1 => 1, // Dawn
-        t if t <= 0.35 => 2, // Day
-        t if t <= 0.45 => 3, // Dusk
-        _ => 4, // Night
-    };
-
-    let prev_day_state = match day_night_cycle.prev_time as f32 {
-        t if t <= 0.1 => 1, // Dawn
-        t if t <= 0.35 => 2, // Day
-        t if t <= 0.45 => 3, // Dusk
-        _ => 4, // Night
-    };
-
-    // Send events if necessary
-    if day_state != prev_day_state {
-        if day_state == 1 {
-            ev_dawn.send(DawnStartEvent);
-        }
-        else if day_state == 2 {
-            ev_day.send(DayStartEvent);
-        }
-        else if day_state == 3 {
-            ev_dusk.send(DuskStartEvent);
-        }
-        else if day_state == 4 {
-            ev_night.send(NightStartEvent);
-        }
-    }
-
-    // Update previous time
-    day_night_cycle.prev_time = time_of_day;
-
-    // Calculate the sun brightness
-    let global_brightness_factor = match time_of_day {
-        t if t <= 0.1 => lerp(NIGHT_LIGHT_LEVEL, DAY_LIGHT_LEVEL, t / 0.1), // Dawn
-        t if t <= 0.35 => DAY_LIGHT_LEVEL, // Day
-        t if t <= 0.45 => lerp(DAY_LIGHT_LEVEL, NIGHT_LIGHT_LEVEL, (t - 0.35) / 0.1), // Dusk
-        _ => NIGHT_LIGHT_LEVEL, // Night
-    };
-
-    let torch_multiplier = match time_of_day {
-        t if t <= 0.1 => lerp(1., 0., t / 0.1), // Dawn
-        t if t <= 0.35 => 0., // Day
-        t if t <= 0.45 => lerp(0., 1., (t - 0.35) / 0.1), // Dusk
-        _ => 1., // Night
-    };
-
-    // Calculate the brightness factor for this tile
-    
-    for (mut sprite, pos) in query.iter_mut() {
-        // Deconstruct the position vector
-        let Vec2 { x, y } = pos.0;
-
-        let (x, y) = map::get_tile(x, y);
-
-        let numbers = vec![
-            // Torches
-            map::distance_int_from_point((7, 6), (x, y)).floor() as i32,
-            map::distance_int_from_point((7, 15), (x, y)).floor() as i32,
-            map::distance_int_from_point((33, 6), (x, y)).floor() as i32,
-            map::distance_int_from_point((33, 15), (x, y)).floor() as i32,
-            // The Base Lights
-            map::distance_int_from_point((13, 3), (x, y)).floor() as i32,
-            map::distance_int_from_point((20, 3), (x, y)).floor() as i32,
-            map::distance_int_from_point((27, 3), (x, y)).floor() as i32,
-            
-        ];
-
-        let mut torch_light_factor = 1.;
-
-        if let Some(min_value) = numbers.iter().cloned().min() {
-            if min_value < 7 {
-                let dist = 7 - min_value;
-                torch_light_factor = 1. + (dist as f32 * (0.1) * torch_multiplier);
-            }
-        }
-        
-        // Calculate total brightness factor (constain torch lights to 7 units away)
-        let brightness_factor = global_brightness_factor * torch_light_factor;
-        // your color changing logic here instead:
-        sprite.color = Color::rgba(
-            1.0 * brightness_factor,
-            1.0 * brightness_factor,
-            1.0 * brightness_factor,
-            1.0, // Preserve the alpha channel
-        );
-    }
-}
-
-fn lerp (a: f32, b: f32, ratio: f32) -> f32 {
-    a + ((b - a) * ratio)
-} 
@@ -0,0 +1,49 @@
+//! The game itself, split from `main.rs` so tests and tools can run it headless.
+
+use bevy::prelude::*;
+
+pub const RESOLUTION_X: f32 = 1312.;
+pub const RESOLUTION_Y: f32 = 704.;
+
+pub mod loading;
+pub mod menu;
+pub mod game;
+pub mod camera;
+pub mod map;
+pub mod day_night;
+pub mod lighting;
+pub mod building;
+pub mod crops;
+pub mod weather;
+pub mod effects;
+pub mod chunk;
+pub mod player;
+pub mod the_core;
+pub mod run;
+pub mod save;
+pub mod sim;
+pub mod replay;
+#[cfg(feature = "audio")]
+pub mod music_player;
+pub mod sfx;
+pub mod tools;
+pub mod bases;
+pub mod undo;
+pub mod walls;
+pub mod pathfinding;
+pub mod enemies;
+#[cfg(feature = "dev")]
+pub mod console;
+#[cfg(feature = "dev")]
+pub mod debug_overlay;
+
+pub mod spriteanims;
+
+#[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, States)]
+pub enum AppState {
+    #[default]
+    Loading,
+    Menu,
+    CharacterSelect,
+    Game,
+}
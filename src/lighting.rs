@@ -0,0 +1,273 @@
+use bevy::prelude::*;
+
+use crate::{day_night::{DayNightCycle, DayNightSchedule, DayPhase}, map::{self, TileMap}, weather::Weather};
+
+const DAY_LIGHT_LEVEL: f32 = 1.5;
+const NIGHT_LIGHT_LEVEL: f32 = 0.6;
+
+/// How a light reacts to the time of day
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LightCurve {
+    /// Same strength all day
+    Always,
+    /// Off during the day, fades in at dusk and out at dawn
+    Nightly,
+}
+
+impl LightCurve {
+    /// Strength multiplier for the current phase of the day
+    pub fn factor(&self, phase: &LightingPhase) -> f32 {
+        match self {
+            LightCurve::Always => 1.,
+            LightCurve::Nightly => phase.night,
+        }
+    }
+}
+
+/// Something that lights up the tiles around it
+#[derive(Component, Clone, Copy, Debug)]
+pub struct LightSource {
+    /// Reach in tiles, brightness falls off to nothing at the edge
+    pub radius: f32,
+    /// Extra brightness added at the center of the light
+    pub intensity: f32,
+    pub color: Color,
+    pub curve: LightCurve,
+}
+
+impl LightSource {
+    pub fn torch() -> LightSource {
+        LightSource { radius: 7., intensity: 1.2, color: Color::rgb(1., 0.75, 0.45), curve: LightCurve::Nightly }
+    }
+
+    pub fn base_light() -> LightSource {
+        LightSource { radius: 7., intensity: 1.2, color: Color::rgb(0.8, 0.9, 1.), curve: LightCurve::Nightly }
+    }
+
+    /// Buildable lamp, reaches further than a torch and stays on all day
+    pub fn lamp() -> LightSource {
+        LightSource { radius: 10., intensity: 1., color: Color::rgb(0.7, 0.85, 1.), curve: LightCurve::Always }
+    }
+}
+
+/// Entity that blocks light on the tile it stands on, like the base walls
+#[derive(Component)]
+pub struct Occluder;
+
+/// Sprite tinted by the light on the tile under it
+#[derive(Component)]
+pub struct Lit;
+
+/// Most extra light a tile can pick up from overlapping lights, per channel
+const MAX_GLOW: f32 = 1.2;
+/// Steps dawn and dusk fade through. The light map only changes once per step rather than every tick.
+const FADE_STEPS: f32 = 24.;
+
+/// Light cast by the light sources on each tile, kept apart from the sun. Only rebuilt
+/// when a light or occluder is added, moved or removed, a new phase just rescales it.
+#[derive(Resource, Default)]
+pub struct LightGlow {
+    width: i32,
+    /// From lights that shine all day
+    always: Vec<Vec3>,
+    /// From lights that follow the night, before the night factor is applied
+    nightly: Vec<Vec3>,
+}
+
+impl LightGlow {
+    /// Glow on a tile for the current phase of the day, per color channel
+    fn at(&self, phase: &LightingPhase, x: i32, y: i32) -> Vec3 {
+        if x < 0 || y < 0 || x >= self.width || (y * self.width + x) as usize >= self.always.len() {
+            return Vec3::ZERO;
+        }
+        let i = (y * self.width + x) as usize;
+        let glow = self.always[i] * LightCurve::Always.factor(phase) + self.nightly[i] * LightCurve::Nightly.factor(phase);
+        glow.min(Vec3::splat(MAX_GLOW))
+    }
+}
+
+/// Lighting inputs that come from the time of day. When only these change the
+/// light map is rescaled from the cached glow, without tracing any light again.
+#[derive(Resource, Clone, Copy, PartialEq, Debug)]
+pub struct LightingPhase {
+    /// Sun brightness applied to every tile
+    pub sun: f32,
+    /// 0 during the day, 1 at night, blended over dawn and dusk
+    pub night: f32,
+}
+
+impl Default for LightingPhase {
+    fn default() -> Self {
+        // Never matches a real phase, so the first frame always computes
+        LightingPhase { sun: f32::NAN, night: f32::NAN }
+    }
+}
+
+impl LightingPhase {
+    /// Dawn and dusk blend between night and day over the length of the phase, a step at a time
+    pub fn at(phase: DayPhase, progress: f32) -> LightingPhase {
+        let progress = (progress * FADE_STEPS).floor() / FADE_STEPS;
+        let (sun, night) = match phase {
+            DayPhase::Dawn => (lerp(NIGHT_LIGHT_LEVEL, DAY_LIGHT_LEVEL, progress), lerp(1., 0., progress)),
+            DayPhase::Day => (DAY_LIGHT_LEVEL, 0.),
+            DayPhase::Dusk => (lerp(DAY_LIGHT_LEVEL, NIGHT_LIGHT_LEVEL, progress), lerp(0., 1., progress)),
+            DayPhase::Night => (NIGHT_LIGHT_LEVEL, 1.),
+        };
+        LightingPhase { sun, night }
+    }
+}
+
+/// Keep the tile light map in step with the light sources and the time of day
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn update_lighting(
+    cycle: Res<DayNightCycle>,
+    schedule: Res<DayNightSchedule>,
+    weather: Res<Weather>,
+    mut phase: ResMut<LightingPhase>,
+    mut light_glow: ResMut<LightGlow>,
+    mut tile_map: ResMut<TileMap>,
+    lights: Query<(&LightSource, &Transform)>,
+    occluders: Query<&Transform, With<Occluder>>,
+    changed: Query<(), Or<(
+        (With<LightSource>, Or<(Changed<LightSource>, Changed<Transform>)>),
+        (With<Occluder>, Changed<Transform>),
+    )>>,
+    mut removed_lights: RemovedComponents<LightSource>,
+    mut removed_occluders: RemovedComponents<Occluder>,
+) {
+    let mut new_phase = LightingPhase::at(cycle.phase, cycle.progress(&schedule));
+    new_phase.sun *= weather.kind.sun_factor();
+    // Drain removals every frame so old ones don't trigger a rebuild later
+    let removed = removed_lights.read().count() + removed_occluders.read().count() > 0;
+    let occlusion_changed = tile_map.take_occlusion_dirty();
+    let scene_changed = !changed.is_empty() || removed || occlusion_changed || tile_map.is_added();
+
+    if new_phase == *phase && !scene_changed {
+        return;
+    }
+    *phase = new_phase;
+
+    let (width, height) = (tile_map.width(), tile_map.height());
+    if scene_changed {
+        *light_glow = trace_lights(&tile_map, &lights, &occluders);
+    }
+    for x in 0..width {
+        for y in 0..height {
+            // Only tiles whose brightness actually changes get their chunk refreshed
+            tile_map.set_light(x, y, (Vec3::ONE + light_glow.at(&phase, x, y)) * phase.sun);
+        }
+    }
+}
+
+/// Splat every light into the grid, sorted by how it reacts to the time of day
+fn trace_lights(
+    tile_map: &TileMap,
+    lights: &Query<(&LightSource, &Transform)>,
+    occluders: &Query<&Transform, With<Occluder>>,
+) -> LightGlow {
+    let (width, height) = (tile_map.width(), tile_map.height());
+    let index = |x: i32, y: i32| (y * width + x) as usize;
+
+    // Tiles that stop light, from the map itself and from blocking entities
+    let mut blocked = vec![false; (width * height) as usize];
+    for x in 0..width {
+        for y in 0..height {
+            blocked[index(x, y)] = tile_map.get(x, y).is_some_and(|tile| tile.blocks_light());
+        }
+    }
+    for transform in occluders.iter() {
+        let (x, y) = map::get_tile(transform.translation.x, transform.translation.y);
+        if x >= 0 && y >= 0 && x < width && y < height {
+            blocked[index(x, y)] = true;
+        }
+    }
+
+    // Only touch tiles within each light's reach
+    let mut light_glow = LightGlow {
+        width,
+        always: vec![Vec3::ZERO; (width * height) as usize],
+        nightly: vec![Vec3::ZERO; (width * height) as usize],
+    };
+    for (light, transform) in lights.iter() {
+        if light.intensity <= 0. {
+            continue;
+        }
+        let [r, g, b, _] = light.color.as_rgba_f32();
+        let color = Vec3::new(r, g, b) * light.intensity;
+        let glow = match light.curve {
+            LightCurve::Always => &mut light_glow.always,
+            LightCurve::Nightly => &mut light_glow.nightly,
+        };
+        let source = map::get_tile(transform.translation.x, transform.translation.y);
+        let reach = light.radius.ceil() as i32;
+
+        for x in (source.0 - reach).max(0)..=(source.0 + reach).min(width - 1) {
+            for y in (source.1 - reach).max(0)..=(source.1 + reach).min(height - 1) {
+                let dist = map::distance_int_from_point(source, (x, y));
+                if dist >= light.radius || !line_of_sight(source, (x, y), |x, y| blocked[index(x, y)]) {
+                    continue;
+                }
+                // Quadratic falloff keeps the center bright and the edge soft
+                let falloff = (1. - dist / light.radius).powi(2);
+                glow[index(x, y)] += color * falloff;
+            }
+        }
+    }
+    light_glow
+}
+
+/// Walk the tiles between two points, true if none of the ones in between block.
+/// The end tiles don't count, so walls are lit on the side facing the light.
+fn line_of_sight(from: (i32, i32), to: (i32, i32), blocked: impl Fn(i32, i32) -> bool) -> bool {
+    let (dx, dy) = ((to.0 - from.0).abs(), -(to.1 - from.1).abs());
+    let (step_x, step_y) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+    let (mut x, mut y) = from;
+    let mut err = dx + dy;
+
+    loop {
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += step_x;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += step_y;
+        }
+        if (x, y) == to {
+            return true;
+        }
+        if blocked(x, y) {
+            return false;
+        }
+    }
+}
+
+/// Tint lit sprites with the light under them, keeping their own alpha
+pub fn light_sprites(
+    tile_map: Res<TileMap>,
+    mut sprites: Query<(&mut Sprite, &GlobalTransform), With<Lit>>,
+) {
+    for (mut sprite, transform) in sprites.iter_mut() {
+        let position = transform.translation();
+        let (x, y) = map::get_tile(position.x, position.y);
+        let light = tile_map.light(x, y);
+        let color = Color::rgba(light.x, light.y, light.z, sprite.color.a());
+        if sprite.color != color {
+            sprite.color = color;
+        }
+    }
+}
+
+/// Light a tile gets from light sources on top of the sun, 0 when none reach it.
+/// Read from the glow itself, so it holds up whatever the sun is doing.
+pub fn glow_at(light_glow: &LightGlow, phase: &LightingPhase, x: i32, y: i32) -> f32 {
+    if phase.night.is_nan() {
+        return 0.;
+    }
+    light_glow.at(phase, x, y).max_element()
+}
+
+fn lerp (a: f32, b: f32, ratio: f32) -> f32 {
+    a + ((b - a) * ratio)
+}
@@ -0,0 +1,371 @@
+use std::path::{Path, PathBuf};
+
+use bevy::{asset::{io::file::FileAssetReader, LoadState, RecursiveDependencyLoadState, UntypedAssetId}, prelude::*, utils::HashMap};
+#[cfg(feature = "audio")]
+use bevy_kira_audio::AudioSource;
+
+use crate::{map::TileKind, replay::ReplayPlayback, spriteanims::{self, AnimationSheet}};
+#[cfg(feature = "audio")]
+use crate::{music_player::Stinger, sfx::Sfx};
+
+use super::AppState;
+
+pub fn build_plugin(app: &mut App) {
+    app
+        .add_systems(OnEnter(AppState::Loading), (start_loading, loading_screen_setup))
+        .add_systems(Update, track_loading.run_if(in_state(AppState::Loading)))
+        .add_systems(OnExit(AppState::Loading), cleanup);
+}
+
+/// Character sprite sheets, each with its clips in a matching `.anim.ron`
+pub const CHARACTER_SHEETS: [(&str, &str); 3] = [
+    ("ed_sheet", "entity/human_profile/ed_sheet.anim.ron"),
+    ("human_base", "entity/human_profile/human_base.anim.ron"),
+    ("zombie_slayer", "entity/human_profile/zombie_slayer.anim.ron"),
+];
+
+/// Enemy sprite sheets, one per kind of enemy
+pub const ENEMY_SHEETS: [(&str, &str); 2] = [
+    ("crawler", "entity/enemies/crawler.anim.ron"),
+    ("brute", "entity/enemies/brute.anim.ron"),
+];
+
+/// Every asset the game uses, loaded (and checked) once up front.
+/// The default is all empty handles, for headless runs that never draw anything.
+#[derive(Resource, Default)]
+pub struct GameAssets {
+    // Fonts
+    pub title_font: Handle<Font>,
+    pub ui_font: Handle<Font>,
+    // Tiles
+    pub tiles: HashMap<TileKind, Handle<Image>>,
+    pub torch: Handle<Image>,
+    pub highlight: Handle<Image>,
+    pub base_corner: Handle<Image>,
+    pub base_wall: Handle<Image>,
+    pub base_roof: Handle<Image>,
+    // Entities, animation sheets by name
+    pub character_sheets: HashMap<&'static str, Handle<AnimationSheet>>,
+    pub enemy_sheets: HashMap<&'static str, Handle<AnimationSheet>>,
+    // Images
+    pub hoes: [Handle<Image>; 3],
+    pub resource: Handle<Image>,
+    pub core_background: Handle<Image>,
+    pub core_orb: Handle<Image>,
+    pub core_spin: Handle<Image>,
+    pub core_death: Handle<Image>,
+    // Music
+    #[cfg(feature = "audio")]
+    pub day_music: Handle<AudioSource>,
+    #[cfg(feature = "audio")]
+    pub night_music: Handle<AudioSource>,
+    #[cfg(feature = "audio")]
+    pub game_over_music: Handle<AudioSource>,
+    /// Drums layered over whichever track is playing
+    #[cfg(feature = "audio")]
+    pub percussion_stem: Handle<AudioSource>,
+    #[cfg(feature = "audio")]
+    pub music_stingers: HashMap<Stinger, Handle<AudioSource>>,
+    // Sound effects, every variation of each
+    #[cfg(feature = "audio")]
+    pub sfx: HashMap<Sfx, Vec<Handle<AudioSource>>>,
+}
+
+impl GameAssets {
+    /// Images that get packed into the tile atlas
+    fn tile_sprites(&self) -> Vec<&Handle<Image>> {
+        let mut sprites: Vec<&Handle<Image>> = self.tiles.values().collect();
+        sprites.extend([&self.torch, &self.base_corner, &self.base_wall, &self.base_roof]);
+        sprites
+    }
+}
+
+/// All tile sprites packed into a single image, empty by default for headless runs
+#[derive(Resource, Default)]
+pub struct TileAtlas {
+    pub image: Handle<Image>,
+    pub layout: Handle<TextureAtlasLayout>,
+    /// Where each source image ended up in the layout
+    indices: HashMap<AssetId<Image>, usize>,
+    rects: Vec<Rect>,
+    size: Vec2,
+}
+
+impl TileAtlas {
+    /// Atlas entry for one of the source images, for use on a sprite
+    pub fn sprite(&self, image: &Handle<Image>) -> TextureAtlas {
+        TextureAtlas {
+            layout: self.layout.clone(),
+            index: self.indices.get(&image.id()).copied().unwrap_or_default(),
+        }
+    }
+
+    /// UV rect of one of the source images, pulled in half a texel to avoid seams
+    pub fn uv(&self, image: &Handle<Image>) -> Rect {
+        let Some(index) = self.indices.get(&image.id()) else { return Rect::default(); };
+        let rect = self.rects[*index];
+        Rect::from_corners((rect.min + 0.5) / self.size, (rect.max - 0.5) / self.size)
+    }
+}
+
+/// Everything still being loaded, with the path it came from for error messages
+#[derive(Resource, Default)]
+struct LoadingAssets(Vec<(UntypedAssetId, &'static str)>);
+
+#[derive(Component)]
+struct LoadingScreen;
+
+#[derive(Component)]
+struct LoadingBar;
+
+/// Hands out handles while checking the file is really there, so a bad path fails before anything runs
+struct ManifestLoader<'a> {
+    server: &'a AssetServer,
+    root: PathBuf,
+    loading: Vec<(UntypedAssetId, &'static str)>,
+    problems: Vec<String>,
+}
+
+impl<'a> ManifestLoader<'a> {
+    fn load<A: Asset>(&mut self, path: &'static str) -> Handle<A> {
+        if let Err(problem) = check_path(&self.root, path) {
+            self.problems.push(problem);
+        }
+        let handle = self.server.load(path);
+        self.loading.push((handle.id().untyped(), path));
+        handle
+    }
+
+    /// An animation sheet, checking the image and clips it points at as well
+    fn load_sheet(&mut self, path: &'static str) -> Handle<AnimationSheet> {
+        let handle = self.load(path);
+        // A missing sheet has already been reported by `load`
+        let Ok(bytes) = std::fs::read(self.root.join(path)) else { return handle; };
+        match spriteanims::sheet_dependencies(&bytes) {
+            Ok(dependencies) => {
+                for dependency in dependencies {
+                    if let Err(problem) = check_path(&self.root, &dependency) {
+                        self.problems.push(format!("{} (from {})", problem, path));
+                    }
+                }
+            }
+            Err(err) => self.problems.push(format!("{}: {}", path, err)),
+        }
+        handle
+    }
+}
+
+/// Makes sure a file exists with exactly this casing, since Linux won't forgive `hoe1.png` for `Hoe1.png`
+pub fn check_path(root: &Path, path: &str) -> Result<(), String> {
+    let mut dir = root.to_path_buf();
+    for part in path.split('/') {
+        let entries: Vec<String> = std::fs::read_dir(&dir)
+            .map(|read| read.filter_map(|e| e.ok()).map(|e| e.file_name().to_string_lossy().into_owned()).collect())
+            .unwrap_or_default();
+
+        if !entries.iter().any(|entry| entry == part) {
+            return match entries.iter().find(|entry| entry.eq_ignore_ascii_case(part)) {
+                Some(found) => Err(format!("{}: wrong case, found '{}' on disk", path, found)),
+                None => Err(format!("{}: missing", path)),
+            };
+        }
+        dir.push(part);
+    }
+    Ok(())
+}
+
+fn start_loading(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let mut loader = ManifestLoader {
+        server: &asset_server,
+        root: FileAssetReader::get_base_path().join("assets"),
+        loading: Vec::new(),
+        problems: Vec::new(),
+    };
+
+    let game_assets = GameAssets {
+        title_font: loader.load("fonts/Disolve_light.ttf"),
+        ui_font: loader.load("fonts/BebasNeue-Regular.ttf"),
+        tiles: TileKind::ALL.iter().map(|kind| (*kind, loader.load(kind.path()))).collect(),
+        torch: loader.load("tiles/torch.png"),
+        highlight: loader.load("tiles/highlight.png"),
+        base_corner: loader.load("tiles/base_corner.png"),
+        base_wall: loader.load("tiles/base_wall.png"),
+        base_roof: loader.load("tiles/base_roof.png"),
+        character_sheets: CHARACTER_SHEETS.iter().map(|(name, path)| (*name, loader.load_sheet(path))).collect(),
+        enemy_sheets: ENEMY_SHEETS.iter().map(|(name, path)| (*name, loader.load_sheet(path))).collect(),
+        hoes: [
+            loader.load("images/Hoe1.png"),
+            loader.load("images/Hoe2.png"),
+            loader.load("images/Hoe3.png"),
+        ],
+        resource: loader.load("images/Resource.png"),
+        core_background: loader.load("images/core_background.png"),
+        core_orb: loader.load("images/core_orb.png"),
+        core_spin: loader.load("images/core_spin.png"),
+        core_death: loader.load("images/core_death.png"),
+        #[cfg(feature = "audio")]
+        day_music: loader.load("music/SolFarmer_4_Track_2.ogg"),
+        #[cfg(feature = "audio")]
+        night_music: loader.load("music/SolFarmer_4_Track.ogg"),
+        #[cfg(feature = "audio")]
+        game_over_music: loader.load("music/game_over.wav"),
+        #[cfg(feature = "audio")]
+        percussion_stem: loader.load("music/percussion_stem.wav"),
+        #[cfg(feature = "audio")]
+        music_stingers: Stinger::ALL.iter().map(|stinger| (*stinger, loader.load(stinger.path()))).collect(),
+        #[cfg(feature = "audio")]
+        sfx: Sfx::ALL.iter().map(|sfx| (*sfx, sfx.paths().iter().map(|path| loader.load(path)).collect())).collect(),
+    };
+
+    if !loader.problems.is_empty() {
+        panic!("Asset manifest check failed under {}:\n  {}", loader.root.display(), loader.problems.join("\n  "));
+    }
+
+    commands.insert_resource(LoadingAssets(loader.loading));
+    commands.insert_resource(game_assets);
+}
+
+/// Update the progress bar, and once everything is in pack the atlas and head to the menu
+#[allow(clippy::too_many_arguments)]
+fn track_loading(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    loading: Res<LoadingAssets>,
+    game_assets: Res<GameAssets>,
+    mut images: ResMut<Assets<Image>>,
+    mut layouts: ResMut<Assets<TextureAtlasLayout>>,
+    mut bar: Query<&mut Style, With<LoadingBar>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    playback: Option<Res<ReplayPlayback>>,
+) {
+    let mut loaded = 0;
+    for (id, path) in loading.0.iter() {
+        if let Some(LoadState::Failed) = asset_server.get_load_state(*id) {
+            panic!("Failed to load asset {}", path);
+        }
+        // Otherwise a sheet whose image fails would sit waiting on it forever
+        if let Some(RecursiveDependencyLoadState::Failed) = asset_server.get_recursive_dependency_load_state(*id) {
+            panic!("Failed to load a file asset {} depends on", path);
+        }
+        if asset_server.is_loaded_with_dependencies(*id) {
+            loaded += 1;
+        }
+    }
+
+    let progress = loaded as f32 / loading.0.len().max(1) as f32;
+    for mut style in bar.iter_mut() {
+        style.width = Val::Percent(progress * 100.);
+    }
+
+    if loaded < loading.0.len() {
+        return;
+    }
+
+    match build_tile_atlas(&game_assets, &mut images, &mut layouts) {
+        Ok(atlas) => commands.insert_resource(atlas),
+        Err(err) => panic!("Failed to build the tile atlas: {}", err),
+    }
+    // Replays skip the menus and go straight into the recorded run
+    next_state.set(if playback.is_some() { AppState::Game } else { AppState::Menu });
+}
+
+fn build_tile_atlas(
+    game_assets: &GameAssets,
+    images: &mut Assets<Image>,
+    layouts: &mut Assets<TextureAtlasLayout>,
+) -> Result<TileAtlas, String> {
+    // Padding keeps neighbouring textures from bleeding in when sampling near the edges
+    let mut builder = TextureAtlasBuilder::default().padding(UVec2::splat(2));
+    for handle in game_assets.tile_sprites() {
+        let image = images.get(handle).ok_or("tile sprite not loaded")?;
+        builder.add_texture(Some(handle.id()), image);
+    }
+    let (layout, image) = builder.finish().map_err(|err| format!("{:?}", err))?;
+
+    let indices = game_assets.tile_sprites().into_iter().filter_map(|handle| {
+        Some((handle.id(), layout.get_texture_index(handle)?))
+    }).collect();
+
+    Ok(TileAtlas {
+        size: layout.size,
+        rects: layout.textures.clone(),
+        indices,
+        image: images.add(image),
+        layout: layouts.add(layout),
+    })
+}
+
+fn loading_screen_setup(mut commands: Commands) {
+    commands.spawn((Camera2dBundle::default(), LoadingScreen));
+    commands.spawn((LoadingScreen, NodeBundle {
+        style: Style {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            flex_direction: FlexDirection::Column,
+            ..default()
+        },
+        ..default()
+    })).with_children(|parent| {
+        // Our fonts aren't in yet, so this uses bevy's built in one
+        parent.spawn(TextBundle {
+            text: Text::from_section("Loading", TextStyle {
+                font_size: 32.0,
+                color: Color::rgb(0.9, 0.9, 0.9),
+                ..default()
+            }),
+            style: Style {
+                margin: UiRect::bottom(Val::Px(20.0)),
+                ..default()
+            },
+            ..default()
+        });
+        // Progress bar track
+        parent.spawn(NodeBundle {
+            style: Style {
+                width: Val::Px(400.0),
+                height: Val::Px(20.0),
+                border: UiRect::all(Val::Px(3.0)),
+                ..default()
+            },
+            border_color: BorderColor(Color::BLACK),
+            background_color: Color::rgb(0.15, 0.15, 0.15).into(),
+            ..default()
+        }).with_children(|parent| {
+            parent.spawn((LoadingBar, NodeBundle {
+                style: Style {
+                    width: Val::Percent(0.0),
+                    height: Val::Percent(100.0),
+                    ..default()
+                },
+                background_color: Color::rgb(0.35, 0.75, 0.35).into(),
+                ..default()
+            }));
+        });
+    });
+}
+
+fn cleanup(mut commands: Commands, query: Query<Entity, With<LoadingScreen>>) {
+    commands.remove_resource::<LoadingAssets>();
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sheets_and_their_files_are_on_disk() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets");
+        for (_, path) in CHARACTER_SHEETS.into_iter().chain(ENEMY_SHEETS) {
+            check_path(&root, path).unwrap();
+            let bytes = std::fs::read(root.join(path)).unwrap();
+            for dependency in spriteanims::sheet_dependencies(&bytes).unwrap() {
+                check_path(&root, &dependency).unwrap();
+            }
+        }
+    }
+}
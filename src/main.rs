@@ -1,31 +1,43 @@
-use bevy::{diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin}, prelude::*, window::{PresentMode, WindowTheme}};
+use bevy::{prelude::*, window::{PresentMode, WindowTheme}};
 use bevy_hanabi::prelude::*;
-use bevy_kira_audio::{Audio, AudioControl, AudioPlugin, AudioSource};
+#[cfg(feature = "audio")]
+use bevy_kira_audio::{AudioApp, AudioPlugin};
 
 use bevy::winit::WinitWindows;
 use winit::window::Icon;
 
-const RESOLUTION_X: f32 = 1312.;
-const RESOLUTION_Y: f32 = 704.;
+#[cfg(feature = "dev")]
+use solfarmer::{console, debug_overlay};
+use solfarmer::{game, loading, menu, replay, run, save, sim, spriteanims, AppState, RESOLUTION_X, RESOLUTION_Y};
+#[cfg(feature = "audio")]
+use solfarmer::sfx;
 
-mod menu;
-mod game;
-mod map;
-mod player;
-mod the_core;
-mod music_player;
-
-mod spriteanims;
+fn main() {
+    // `SolFarmer simulate ...` plays a headless run instead of opening the game
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).is_some_and(|arg| arg == "simulate") {
+        sim::cli(&args[2..]);
+        return;
+    }
 
-#[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, States)]
-enum AppState {
-    #[default]
-    Menu,
-    Game,
-}
+    let mut app = App::new();
+    // `SolFarmer replay [file]` watches a recorded run, the last one played by default
+    if args.get(1).is_some_and(|arg| arg == "replay") {
+        let path = args.get(2).map_or_else(replay::Replay::default_path, std::path::PathBuf::from);
+        let recording = match replay::Replay::load(&path) {
+            Ok(recording) => recording,
+            Err(err) => {
+                eprintln!("Could not read replay {}: {}", path.display(), err);
+                std::process::exit(1);
+            }
+        };
+        app.insert_resource(recording.run())
+            .insert_resource(replay::ReplayPlayback::new(recording));
+    }
+    #[cfg(feature = "dev")]
+    app.add_plugins((console::build_plugin, debug_overlay::build_plugin));
 
-fn main() {
-    App::new()
+    app
         .add_plugins((
             DefaultPlugins.set(WindowPlugin {
                 primary_window: Some(Window {
@@ -53,12 +65,18 @@ fn main() {
             //FrameTimeDiagnosticsPlugin,
         ))
         .add_plugins(HanabiPlugin) // Particle Effect System
-        .add_plugins(AudioPlugin)
+        .add_plugins(spriteanims::build_plugin)
+        .add_plugins(loading::build_plugin)
         .add_plugins(menu::build_plugin)
         .add_plugins(game::build_plugin)
         .add_systems(Startup, set_window_icon) // Set the application icon
-        .init_state::<AppState>()
-        .run();
+        .insert_resource(save::SaveData::load())
+        .init_resource::<run::Run>()
+        .init_state::<AppState>();
+    #[cfg(feature = "audio")]
+    app.add_plugins(AudioPlugin)
+        .add_audio_channel::<sfx::SfxChannel>();
+    app.run();
 }
 
 
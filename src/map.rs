@@ -1,8 +1,54 @@
-use bevy::{asset, prelude::*};
+use bevy::{prelude::*, utils::HashSet};
+
+use crate::{bases::{BaseLight, BaseRoof, BASE_TILES}, effects::Embers, lighting::{LightSource, Lit, Occluder}, loading::{GameAssets, TileAtlas}};
 
 use super::{RESOLUTION_X, RESOLUTION_Y};
 
-#[derive(Component)]
+/// Tile size in world units
+pub const TILE_SIZE: f32 = 32.;
+
+/// The homestead (base, core, starting field) is the original one screen layout
+pub const HOME_WIDTH: i32 = (RESOLUTION_X / TILE_SIZE) as i32;
+pub const HOME_HEIGHT: i32 = (RESOLUTION_Y / TILE_SIZE) as i32;
+
+/// Size of the playable map in tiles
+#[derive(Resource, Clone, Copy)]
+pub struct MapSize {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Default for MapSize {
+    fn default() -> Self {
+        MapSize { width: 72, height: 44 }
+    }
+}
+
+impl MapSize {
+    /// Bottom left tile of the homestead, which sits centered along the bottom of the map
+    pub fn home_origin(&self) -> (i32, i32) {
+        (((self.width - HOME_WIDTH) / 2).max(0), 0)
+    }
+
+    /// Convert a homestead local tile into a map tile
+    pub fn home(&self, x: i32, y: i32) -> (i32, i32) {
+        let (o_x, o_y) = self.home_origin();
+        (x + o_x, y + o_y)
+    }
+
+    /// World space rectangle covered by the map (tiles are centered on their world coords)
+    pub fn world_bounds(&self) -> Rect {
+        let half = TILE_SIZE / 2.;
+        Rect::new(
+            -half,
+            -half,
+            self.width as f32 * TILE_SIZE - half,
+            self.height as f32 * TILE_SIZE - half,
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum TileState {
     Immutable,
     Untoiled,
@@ -10,42 +56,191 @@ pub enum TileState {
     Planted,
 }
 
-impl TileState {
-    pub fn value(&self) -> String {
+/// Which texture a tile is drawn with
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum TileKind {
+    RedGrass,
+    FarmTile,
+    SeedsGreen,
+    SeedsPink,
+    SeedsYellow,
+    Concrete,
+    WoodRuined,
+}
+
+impl TileKind {
+    pub const ALL: [TileKind; 7] = [
+        TileKind::RedGrass,
+        TileKind::FarmTile,
+        TileKind::SeedsGreen,
+        TileKind::SeedsPink,
+        TileKind::SeedsYellow,
+        TileKind::Concrete,
+        TileKind::WoodRuined,
+    ];
+
+    pub fn path(&self) -> &'static str {
         match self {
-            TileState::Immutable => String::from("Immutable"),
-            TileState::Untoiled => String::from("Untoiled"),
-            TileState::Toiled => String::from("Toiled"),
-            TileState::Planted => String::from("Planted"),
+            TileKind::RedGrass => "tiles/redgrass.png",
+            TileKind::FarmTile => "tiles/farmtile.png",
+            TileKind::SeedsGreen => "tiles/farmtile_seeds_green.png",
+            TileKind::SeedsPink => "tiles/farmtile_seeds_pink.png",
+            TileKind::SeedsYellow => "tiles/farmtile_seeds_yellow.png",
+            TileKind::Concrete => "tiles/concrete.png",
+            TileKind::WoodRuined => "tiles/wood_ruined.png",
         }
     }
 }
 
-#[derive(Component)]
-pub struct Position(pub Vec2);
-
-#[derive(Bundle)]
-pub struct TileBundle {
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Tile {
+    pub kind: TileKind,
     pub state: TileState,
-    pub sprite: SpriteBundle,
-    pub position: Position,
 }
 
-impl TileBundle {
-    pub fn new (texture: Handle<Image>, x: i32, y: i32, state: TileState) -> TileBundle {
-        // Get the tile coords
-        let (x, y) = get_world(x, y);
-        // Return the tile bundle
-        TileBundle {
-            sprite: SpriteBundle {
-                texture:texture,
-                transform: Transform::from_xyz(x, y, 0.),
-                ..default()
-            },
-            state: state,
-            position: Position(Vec2::new(x, y))
+impl Tile {
+    pub fn new(kind: TileKind, state: TileState) -> Tile {
+        Tile { kind, state }
+    }
+
+    /// Whether the tile casts a shadow. Concrete is paving, so light passes over it.
+    pub fn blocks_light(&self) -> bool {
+        self.state == TileState::Immutable && self.kind != TileKind::Concrete
+    }
+}
+
+/// Number of tiles along each side of a render chunk
+pub const CHUNK_SIZE: i32 = 16;
+
+/// All of the tiles on the map, the single source of truth for tile state.
+/// Edits mark their chunk dirty so only that chunk gets rebuilt.
+#[derive(Resource)]
+pub struct TileMap {
+    width: i32,
+    height: i32,
+    tiles: Vec<Tile>,
+    /// Brightness factor of each tile per color channel, written by the lighting
+    light: Vec<Vec3>,
+    dirty_chunks: HashSet<IVec2>,
+    light_dirty_chunks: HashSet<IVec2>,
+    /// Set when a tile starts or stops blocking light
+    occlusion_dirty: bool,
+    /// Tiles as they were before each edit, while something's listening
+    journal: Option<Vec<((i32, i32), Tile)>>,
+}
+
+impl TileMap {
+    pub fn new(size: &MapSize, fill: Tile) -> TileMap {
+        let count = (size.width * size.height) as usize;
+        let mut map = TileMap {
+            width: size.width,
+            height: size.height,
+            tiles: vec![fill; count],
+            light: vec![Vec3::ONE; count],
+            dirty_chunks: HashSet::new(),
+            light_dirty_chunks: HashSet::new(),
+            occlusion_dirty: true,
+            journal: None,
+        };
+        map.mark_all_dirty();
+        map
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return None;
         }
+        Some((y * self.width + x) as usize)
+    }
+
+    /// Every tile, row by row
+    pub fn tiles(&self) -> &[Tile] {
+        &self.tiles
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> Option<&Tile> {
+        self.index(x, y).map(|i| &self.tiles[i])
     }
+
+    /// Replace a tile, flagging its chunk for a rebuild if anything changed
+    pub fn set(&mut self, x: i32, y: i32, tile: Tile) {
+        let Some(i) = self.index(x, y) else { return; };
+        if self.tiles[i] != tile {
+            if self.tiles[i].blocks_light() != tile.blocks_light() {
+                self.occlusion_dirty = true;
+            }
+            if let Some(journal) = &mut self.journal {
+                journal.push(((x, y), self.tiles[i]));
+            }
+            self.tiles[i] = tile;
+            self.dirty_chunks.insert(chunk_of(x, y));
+        }
+    }
+
+    /// Start noting down every tile edit, so they can be taken back
+    pub fn start_journal(&mut self) {
+        self.journal = Some(Vec::new());
+    }
+
+    /// Stop noting edits, and get back each edited tile as it was, oldest edit first
+    pub fn take_journal(&mut self) -> Vec<((i32, i32), Tile)> {
+        self.journal.take().unwrap_or_default()
+    }
+
+    pub fn light(&self, x: i32, y: i32) -> Vec3 {
+        self.index(x, y).map_or(Vec3::ONE, |i| self.light[i])
+    }
+
+    pub fn set_light(&mut self, x: i32, y: i32, value: Vec3) {
+        let Some(i) = self.index(x, y) else { return; };
+        if self.light[i] != value {
+            self.light[i] = value;
+            self.light_dirty_chunks.insert(chunk_of(x, y));
+        }
+    }
+
+    /// Number of chunks along each axis
+    pub fn chunk_count(&self) -> IVec2 {
+        IVec2::new(
+            (self.width + CHUNK_SIZE - 1) / CHUNK_SIZE,
+            (self.height + CHUNK_SIZE - 1) / CHUNK_SIZE,
+        )
+    }
+
+    pub fn mark_all_dirty(&mut self) {
+        let count = self.chunk_count();
+        for x in 0..count.x {
+            for y in 0..count.y {
+                self.dirty_chunks.insert(IVec2::new(x, y));
+            }
+        }
+    }
+
+    pub fn take_dirty_chunks(&mut self) -> Vec<IVec2> {
+        self.dirty_chunks.drain().collect()
+    }
+
+    pub fn take_light_dirty_chunks(&mut self) -> Vec<IVec2> {
+        self.light_dirty_chunks.drain().collect()
+    }
+
+    /// True once after any change to which tiles block light
+    pub fn take_occlusion_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.occlusion_dirty)
+    }
+}
+
+/// The chunk a tile lives in
+pub fn chunk_of(x: i32, y: i32) -> IVec2 {
+    IVec2::new(x.div_euclid(CHUNK_SIZE), y.div_euclid(CHUNK_SIZE))
 }
 
 
@@ -75,137 +270,86 @@ fn within_rect(bottom_left: (i32, i32), top_right: (i32, i32), point: (i32, i32)
     (pt_x >= btm_x && pt_x <= top_x) && (pt_y >= btm_y && pt_y <= top_y)
 }
 
-pub fn draw_background (mut commands: Commands, assets: Res<AssetServer>) {
-    let num_x = (RESOLUTION_X / 32.) as i32;
-    let num_y = (RESOLUTION_Y / 32.) as i32;
+pub fn draw_background (mut commands: Commands, game_assets: &GameAssets, atlas: &TileAtlas, map_size: &MapSize) {
+    let num_x = map_size.width;
+    let num_y = map_size.height;
 
-    let middle_tile = (num_x / 2, num_y / 2);
+    let middle_tile = map_size.home(HOME_WIDTH / 2, HOME_HEIGHT / 2);
     let rock_pos_1 = (0, 0);
     let rock_pos_2 = (0, num_y - 1);
     let rock_pos_3 = (num_x - 1, 0);
     let rock_pos_4 = (num_x - 1, num_y - 1);
 
+    // The house and torches are laid out in homestead coordinates
+    let house_1 = (map_size.home(6, 0), map_size.home(34, 3));
+    let house_2 = (map_size.home(9, 4), map_size.home(31, 4));
+    let torches = [map_size.home(7, 6), map_size.home(7, 15), map_size.home(33, 6), map_size.home(33, 15)];
+
+    // Start with the basic ground behind everything
+    let mut tile_map = TileMap::new(map_size, Tile::new(TileKind::RedGrass, TileState::Untoiled));
+
     // Load the background
     for x in 0..num_x {
         for y in 0..num_y {
             // Draw the Corners of the map
             if within_circle(rock_pos_1, (x, y), 5.) || within_circle(rock_pos_2, (x, y), 5.) || within_circle(rock_pos_3, (x, y), 5.) || within_circle(rock_pos_4, (x, y), 5.) {
-                commands.spawn(TileBundle::new(
-                    assets.load("tiles/wood_ruined.png"),
-                    x,
-                    y,
-                    TileState::Immutable
-                ));
+                tile_map.set(x, y, Tile::new(TileKind::WoodRuined, TileState::Immutable));
             }
             // Draw the Middle Area (Ready Planting Zone)            
             else if within_circle(middle_tile, (x, y), 4.) {
-                commands.spawn(TileBundle::new(
-                    assets.load("tiles/farmtile.png"),
-                    x,
-                    y,
-                    TileState::Toiled   
-                ));
+                tile_map.set(x, y, Tile::new(TileKind::FarmTile, TileState::Toiled));
             }
             // Draw the "house" at the bottom of the map
-            else if within_rect((6, 0), (34, 3), (x, y)) || within_rect((9, 4), (31, 4), (x, y)) {
-                commands.spawn(TileBundle::new(
-                    assets.load("tiles/concrete.png"),
-                    x,
-                    y,
-                    TileState::Immutable
-                ));
+            else if within_rect(house_1.0, house_1.1, (x, y)) || within_rect(house_2.0, house_2.1, (x, y)) {
+                tile_map.set(x, y, Tile::new(TileKind::Concrete, TileState::Immutable));
             } 
             // Place Torches on Concrete tiles
-            else if (x == 7 || x == 33) && (y == 6 || y == 15) {
-                commands.spawn(TileBundle::new(
-                    assets.load("tiles/concrete.png"),
-                    x,
-                    y,
-                    TileState::Immutable
-                ));
-                commands.spawn( SpriteBundle {
-                    texture: assets.load("tiles/torch.png"),
-                    transform: Transform::from_xyz(x as f32 * 32.0, y as f32 * 32.0, 1.),
-                    ..default()
-                });
+            else if torches.contains(&(x, y)) {
+                tile_map.set(x, y, Tile::new(TileKind::Concrete, TileState::Immutable));
+                commands.spawn((make_sprite_bundle(&game_assets.torch, atlas, x as f32 * 32.0, y as f32 * 32.0, 1., 0.), LightSource::torch(), Embers));
             } 
-            // Draw the basic ground behind everything
-            else {
-                commands.spawn(TileBundle::new(
-                    assets.load("tiles/redgrass.png"),
-                    x,
-                    y,
-                    TileState::Untoiled
-                )); 
-            }
         }
     }
 
-    // Save the base centers
-    let (base_1_x, base_1_y) = (13., 1.);
-    let (base_2_x, base_2_y) = (27., 1.);
-    // Draw Base 1
-    // Top Left Corner
-    commands.spawn((make_sprite_bundle("tiles/base_corner.png".to_owned(), &assets, (base_1_x - 3.) * 32.0, (base_1_y + 1.) * 32.0, 5., 90.), Position(Vec2::new(-10., -10.))));
-    // Top Right Corner
-    commands.spawn((make_sprite_bundle("tiles/base_corner.png".to_owned(), &assets, (base_1_x + 3.) * 32.0, (base_1_y + 1.) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    // Left Wall
-    commands.spawn((make_sprite_bundle("tiles/base_wall.png".to_owned(), &assets, (base_1_x - 3.) * 32.0, (base_1_y) * 32.0, 5., 90.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_wall.png".to_owned(), &assets, (base_1_x - 3.) * 32.0, (base_1_y - 1.) * 32.0, 5., 90.), Position(Vec2::new(-10., -10.))));
-    // Top Wall
-    commands.spawn((make_sprite_bundle("tiles/base_wall.png".to_owned(), &assets, (base_1_x - 2.) * 32.0, (base_1_y + 1.) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_wall.png".to_owned(), &assets, (base_1_x - 1.) * 32.0, (base_1_y + 1.) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_wall.png".to_owned(), &assets, (base_1_x) * 32.0, (base_1_y + 1.) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_wall.png".to_owned(), &assets, (base_1_x + 1.) * 32.0, (base_1_y + 1.) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_wall.png".to_owned(), &assets, (base_1_x + 2.) * 32.0, (base_1_y + 1.) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    // Right Wall
-    commands.spawn((make_sprite_bundle("tiles/base_wall.png".to_owned(), &assets, (base_1_x + 3.) * 32.0, (base_1_y) * 32.0, 5., -90.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_wall.png".to_owned(), &assets, (base_1_x + 3.) * 32.0, (base_1_y - 1.) * 32.0, 5., -90.), Position(Vec2::new(-10., -10.))));
-    // Draw Roof
-    commands.spawn((make_sprite_bundle("tiles/base_roof.png".to_owned(), &assets, (base_1_x - 2.) * 32.0, (base_1_y) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_roof.png".to_owned(), &assets, (base_1_x - 1.) * 32.0, (base_1_y) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_roof.png".to_owned(), &assets, (base_1_x) * 32.0, (base_1_y) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_roof.png".to_owned(), &assets, (base_1_x + 1.) * 32.0, (base_1_y) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_roof.png".to_owned(), &assets, (base_1_x + 2.) * 32.0, (base_1_y) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_roof.png".to_owned(), &assets, (base_1_x - 2.) * 32.0, (base_1_y - 1.) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_roof.png".to_owned(), &assets, (base_1_x - 1.) * 32.0, (base_1_y - 1.) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_roof.png".to_owned(), &assets, (base_1_x) * 32.0, (base_1_y - 1.) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_roof.png".to_owned(), &assets, (base_1_x + 1.) * 32.0, (base_1_y - 1.) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_roof.png".to_owned(), &assets, (base_1_x + 2.) * 32.0, (base_1_y - 1.) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-
-    // Draw Base 2
-    // Top Left Corner
-    commands.spawn((make_sprite_bundle("tiles/base_corner.png".to_owned(), &assets, (base_2_x - 3.) * 32.0, (base_2_y + 1.) * 32.0, 5., 90.), Position(Vec2::new(-10., -10.))));
-    // Top Right Corner
-    commands.spawn((make_sprite_bundle("tiles/base_corner.png".to_owned(), &assets, (base_2_x + 3.) * 32.0, (base_2_y + 1.) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    // Left Wall
-    commands.spawn((make_sprite_bundle("tiles/base_wall.png".to_owned(), &assets, (base_2_x - 3.) * 32.0, (base_2_y) * 32.0, 5., 90.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_wall.png".to_owned(), &assets, (base_2_x - 3.) * 32.0, (base_2_y - 1.) * 32.0, 5., 90.), Position(Vec2::new(-10., -10.))));
-    // Top Wall
-    commands.spawn((make_sprite_bundle("tiles/base_wall.png".to_owned(), &assets, (base_2_x - 2.) * 32.0, (base_2_y + 1.) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_wall.png".to_owned(), &assets, (base_2_x - 1.) * 32.0, (base_2_y + 1.) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_wall.png".to_owned(), &assets, (base_2_x) * 32.0, (base_2_y + 1.) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_wall.png".to_owned(), &assets, (base_2_x + 1.) * 32.0, (base_2_y + 1.) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_wall.png".to_owned(), &assets, (base_2_x + 2.) * 32.0, (base_2_y + 1.) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    // Right Wall
-    commands.spawn((make_sprite_bundle("tiles/base_wall.png".to_owned(), &assets, (base_2_x + 3.) * 32.0, (base_2_y) * 32.0, 5., -90.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_wall.png".to_owned(), &assets, (base_2_x + 3.) * 32.0, (base_2_y - 1.) * 32.0, 5., -90.), Position(Vec2::new(-10., -10.))));
-    // Draw Roof
-    commands.spawn((make_sprite_bundle("tiles/base_roof.png".to_owned(), &assets, (base_2_x - 2.) * 32.0, (base_2_y) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_roof.png".to_owned(), &assets, (base_2_x - 1.) * 32.0, (base_2_y) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_roof.png".to_owned(), &assets, (base_2_x) * 32.0, (base_2_y) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_roof.png".to_owned(), &assets, (base_2_x + 1.) * 32.0, (base_2_y) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_roof.png".to_owned(), &assets, (base_2_x + 2.) * 32.0, (base_2_y) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_roof.png".to_owned(), &assets, (base_2_x - 2.) * 32.0, (base_2_y - 1.) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_roof.png".to_owned(), &assets, (base_2_x - 1.) * 32.0, (base_2_y - 1.) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_roof.png".to_owned(), &assets, (base_2_x) * 32.0, (base_2_y - 1.) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_roof.png".to_owned(), &assets, (base_2_x + 1.) * 32.0, (base_2_y - 1.) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-    commands.spawn((make_sprite_bundle("tiles/base_roof.png".to_owned(), &assets, (base_2_x + 2.) * 32.0, (base_2_y - 1.) * 32.0, 5., 0.), Position(Vec2::new(-10., -10.))));
-
-    // Draw the core background
-    //commands.spawn((make_sprite_bundle("images/core_background.png".to_owned(), &assets, (num_x / 2) as f32 * 32., 2. * 32., 1., 0.), Position(Vec2::new(-10., -10.))));
-}
+    commands.insert_resource(tile_map);
 
+    // The base lights have no sprite of their own, they just shine. The ones over the bases go out if they fall.
+    for (light, home) in [(Some(0), (13, 3)), (None, (20, 3)), (Some(1), (27, 3))] {
+        let (x, y) = map_size.home(home.0, home.1);
+        let (x, y) = get_world(x, y);
+        let mut light_entity = commands.spawn((Transform::from_xyz(x, y, 0.), LightSource::base_light()));
+        if let Some(base) = light {
+            light_entity.insert(BaseLight(base));
+        }
+    }
+
+    // Draw the bases, corners and walls around a roof
+    for (base, home) in BASE_TILES.iter().enumerate() {
+        let (base_x, base_y) = map_size.home(home.0, home.1);
+        let (base_x, base_y) = (base_x as f32, base_y as f32);
+        // Top Left Corner
+        commands.spawn((make_sprite_bundle(&game_assets.base_corner, atlas, (base_x - 3.) * 32.0, (base_y + 1.) * 32.0, 5., 90.), Lit, Occluder));
+        // Top Right Corner
+        commands.spawn((make_sprite_bundle(&game_assets.base_corner, atlas, (base_x + 3.) * 32.0, (base_y + 1.) * 32.0, 5., 0.), Lit, Occluder));
+        // Left and Right Walls
+        for y in [base_y, base_y - 1.] {
+            commands.spawn((make_sprite_bundle(&game_assets.base_wall, atlas, (base_x - 3.) * 32.0, y * 32.0, 5., 90.), Lit, Occluder));
+            commands.spawn((make_sprite_bundle(&game_assets.base_wall, atlas, (base_x + 3.) * 32.0, y * 32.0, 5., -90.), Lit, Occluder));
+        }
+        // Top Wall
+        for x in -2..=2 {
+            commands.spawn((make_sprite_bundle(&game_assets.base_wall, atlas, (base_x + x as f32) * 32.0, (base_y + 1.) * 32.0, 5., 0.), Lit, Occluder));
+        }
+        // Draw Roof, which falls in a piece at a time as the base is damaged
+        for (piece, (x, y)) in (-2..=2).flat_map(|x| [(x, 0.), (x, -1.)]).enumerate() {
+            commands.spawn((
+                make_sprite_bundle(&game_assets.base_roof, atlas, (base_x + x as f32) * 32.0, (base_y + y) * 32.0, 5., 0.),
+                Lit,
+                BaseRoof { base, piece },
+            ));
+        }
+    }
+}
 
 pub fn get_tile(x:f32, y:f32) -> (i32, i32) {
     let x = x + 16.;
@@ -217,13 +361,14 @@ pub fn get_world(x:i32, y:i32) -> (f32, f32) {
    ( (x * 32 ) as f32, (y*32) as f32)
 }
 
-fn make_sprite_bundle (path: String, assets: &Res<AssetServer>, x:f32, y:f32, z: f32, angle: f32) -> SpriteBundle {
+fn make_sprite_bundle (image: &Handle<Image>, atlas: &TileAtlas, x:f32, y:f32, z: f32, angle: f32) -> SpriteSheetBundle {
     // Make the transform and rotate it
-    let mut trans = Transform::from_xyz(x, y, 5.);
+    let mut trans = Transform::from_xyz(x, y, z);
     trans.rotate_local_z(angle.to_radians());
-    // Return the sprite bundle
-    SpriteBundle {
-        texture: assets.load(path),
+    // Return the sprite bundle, drawn out of the tile atlas
+    SpriteSheetBundle {
+        texture: atlas.image.clone(),
+        atlas: atlas.sprite(image),
         transform: trans,
         ..default()
     }
@@ -3,7 +3,19 @@
 
 use bevy::{app::AppExit, prelude::*};
 
-use crate::player::{Player, PlayerTool};
+use crate::{
+    building::Buildable,
+    day_night::TimeControlEvent,
+    game::Stockpile,
+    loading::GameAssets,
+    player::{self, Player, PlayerTool},
+    replay::ReplayPlayback,
+    run::{CharacterProfile, Run},
+    save::SaveData,
+    sfx::SfxSettings,
+    tools::{ToolShopEvent, Tools},
+    spriteanims::AnimationSheet,
+};
 
 use super::{AppState, RESOLUTION_X, RESOLUTION_Y};
 
@@ -13,8 +25,11 @@ pub fn build_plugin(app: &mut App) {
         .add_systems(OnEnter(AppState::Game), hud_setup)
         .add_systems(Update, (play_button_system).run_if(in_state(AppState::Menu)))
         .add_systems(Update, (quit_button_system).run_if(in_state(AppState::Menu)))
-        .add_systems(Update, (hud_update).run_if(in_state(AppState::Game)))
-        .add_systems(OnExit(AppState::Menu), cleanup);
+        .add_systems(Update, (hud_update, durability_label).run_if(in_state(AppState::Game)))
+        .add_systems(OnExit(AppState::Menu), cleanup)
+        .add_systems(OnEnter(AppState::CharacterSelect), character_select_setup)
+        .add_systems(Update, (character_button_system, tool_wear_button_system).run_if(in_state(AppState::CharacterSelect)))
+        .add_systems(OnExit(AppState::CharacterSelect), cleanup);
 }
 
 #[derive(Component)]
@@ -23,6 +38,21 @@ struct PlayButton;
 #[derive(Component)]
 struct QuitButton;
 
+#[derive(Component)]
+struct CharacterButton(CharacterProfile);
+
+/// Turns tool wear on and off for the next run
+#[derive(Component)]
+struct ToolWearButton;
+
+/// Text on the tool wear button
+#[derive(Component)]
+struct ToolWearLabel;
+
+fn tool_wear_text(on: bool) -> &'static str {
+    if on { "Tool wear: On" } else { "Tool wear: Off" }
+}
+
 #[derive(Component)]
 enum HUDButtonAction {
     Tiller,
@@ -30,10 +60,23 @@ enum HUDButtonAction {
     Unit2,
     Unit3,
     Unit4,
+    Wall,
     Music,
     SoundEffects,
+    Speed,
+    ReadyForNight,
+    UpgradeHoe,
+    RepairHoe,
 }
 
+/// Text on the speed button, shows the current game speed
+#[derive(Component)]
+struct SpeedLabel;
+
+/// Text on the repair button, shows the uses the hoe has left
+#[derive(Component)]
+struct DurabilityLabel;
+
 #[derive(Component)]
 struct ResourceCounter;
 
@@ -41,6 +84,7 @@ const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
 const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
 const PRESSED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
 
+#[allow(clippy::type_complexity)]
 fn play_button_system(
     mut interaction_query: Query<
         (
@@ -62,7 +106,7 @@ fn play_button_system(
                 text.sections[0].value = "Play".to_string();
                 *color = PRESSED_BUTTON.into();
                 border_color.0 = Color::RED;
-                game_state.set(AppState::Game)
+                game_state.set(AppState::CharacterSelect)
             }
             Interaction::Hovered => {
                 text.sections[0].value = "Play".to_string();
@@ -78,6 +122,7 @@ fn play_button_system(
     }
 }
 
+#[allow(clippy::type_complexity)]
 fn quit_button_system(
     mut interaction_query: Query<
         (
@@ -90,7 +135,6 @@ fn quit_button_system(
     >,
     mut app_exit_events: EventWriter<AppExit>,
     mut text_query: Query<&mut Text, With<QuitButton>>,
-    mut game_state: ResMut<NextState<AppState>>,
 ) {
     // for (interaction, mut color, mut border_color, children) in &mut interaction_query
     for (interaction, mut color, mut border_color, _children) in interaction_query.iter_mut() {
@@ -116,7 +160,7 @@ fn quit_button_system(
     }
 }
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn setup(mut commands: Commands, game_assets: Res<GameAssets>) {
     // ui camera
     commands.spawn(Camera2dBundle::default());
     commands
@@ -137,7 +181,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                 text: Text::from_section(
                     "SolFarmer",
                     TextStyle {
-                        font: asset_server.load("fonts/Disolve_light.ttf"),
+                        font: game_assets.title_font.clone(),
                         font_size: 60.0,
                         color: Color::WHITE,
                     },
@@ -169,7 +213,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                     parent.spawn((PlayButton, TextBundle::from_section(
                         "Play",
                         TextStyle {
-                            font: asset_server.load("fonts/BebasNeue-Regular.ttf"),
+                            font: game_assets.ui_font.clone(),
                             font_size: 40.0,
                             color: Color::rgb(0.9, 0.9, 0.9),
                         },
@@ -198,7 +242,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                     parent.spawn((QuitButton, TextBundle::from_section(
                         "Quit",
                         TextStyle {
-                            font: asset_server.load("fonts/BebasNeue-Regular.ttf"),
+                            font: game_assets.ui_font.clone(),
                             font_size: 40.0,
                             color: Color::rgb(0.9, 0.9, 0.9),
                         },
@@ -207,7 +251,204 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         });
 }
 
-fn hud_setup(mut commands: Commands, assets: Res<AssetServer>) {
+fn character_button_system(
+    mut interaction_query: Query<
+        (
+            &Interaction,
+            &mut BackgroundColor,
+            &mut BorderColor,
+            &CharacterButton,
+        ),
+        Changed<Interaction>,
+    >,
+    mut commands: Commands,
+    mut save: ResMut<SaveData>,
+    mut game_state: ResMut<NextState<AppState>>,
+) {
+    for (interaction, mut color, mut border_color, button) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
+                border_color.0 = Color::RED;
+                // Remember the pick for this run and the next time we play
+                commands.insert_resource(Run { tool_wear: save.tool_wear, ..Run::new(button.0) });
+                save.last_profile = button.0;
+                save.write();
+                game_state.set(AppState::Game);
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+                border_color.0 = Color::GOLD;
+            }
+            Interaction::None => {
+                *color = NORMAL_BUTTON.into();
+                border_color.0 = Color::BLACK;
+            }
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn tool_wear_button_system(
+    mut interaction_query: Query<
+        (
+            &Interaction,
+            &mut BackgroundColor,
+            &mut BorderColor,
+        ),
+        (Changed<Interaction>, With<ToolWearButton>),
+    >,
+    mut text_query: Query<&mut Text, With<ToolWearLabel>>,
+    mut save: ResMut<SaveData>,
+) {
+    for (interaction, mut color, mut border_color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
+                border_color.0 = Color::RED;
+                save.tool_wear = !save.tool_wear;
+                save.write();
+                for mut text in text_query.iter_mut() {
+                    text.sections[0].value = tool_wear_text(save.tool_wear).to_string();
+                }
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+                border_color.0 = Color::GOLD;
+            }
+            Interaction::None => {
+                *color = NORMAL_BUTTON.into();
+                border_color.0 = Color::BLACK;
+            }
+        }
+    }
+}
+
+fn character_select_setup(
+    mut commands: Commands,
+    game_assets: Res<GameAssets>,
+    sheets: Res<Assets<AnimationSheet>>,
+    save: Res<SaveData>,
+) {
+    // ui camera
+    commands.spawn(Camera2dBundle::default());
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(
+                    "Choose your farmer",
+                    TextStyle {
+                        font: game_assets.title_font.clone(),
+                        font_size: 48.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                style: Style {
+                    margin: UiRect::bottom(Val::Px(40.0)),
+                    ..default()
+                },
+                ..default()
+            });
+            parent.spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    ..default()
+                },
+                ..default()
+            }).with_children(|parent| {
+                for profile in CharacterProfile::ALL {
+                    // Last run's pick starts out highlighted
+                    let border = if profile == save.last_profile { Color::GOLD } else { Color::BLACK };
+                    parent
+                        .spawn((CharacterButton(profile), ButtonBundle {
+                            style: Style {
+                                width: Val::Px(200.0),
+                                height: Val::Px(200.0),
+                                border: UiRect::all(Val::Px(5.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                flex_direction: FlexDirection::Column,
+                                margin: UiRect::horizontal(Val::Px(15.0)),
+                                ..default()
+                            },
+                            border_color: BorderColor(border),
+                            background_color: NORMAL_BUTTON.into(),
+                            ..default()
+                        }))
+                        .with_children(|parent| {
+                            // Portrait is the facing down frame of the profile's sheet
+                            if let Some(sheet) = sheets.get(&game_assets.character_sheets[profile.sheet()]) {
+                                parent.spawn(AtlasImageBundle {
+                                    style: Style {
+                                        width: Val::Px(64.0),
+                                        height: Val::Px(64.0),
+                                        margin: UiRect::bottom(Val::Px(10.0)),
+                                        ..default()
+                                    },
+                                    image: UiImage::new(sheet.image.clone()),
+                                    texture_atlas: TextureAtlas { layout: sheet.layout.clone(), index: 0 },
+                                    ..default()
+                                });
+                            }
+                            parent.spawn(TextBundle::from_section(
+                                profile.name(),
+                                TextStyle {
+                                    font: game_assets.ui_font.clone(),
+                                    font_size: 32.0,
+                                    color: Color::rgb(0.9, 0.9, 0.9),
+                                },
+                            ));
+                            parent.spawn(TextBundle::from_section(
+                                profile.description(),
+                                TextStyle {
+                                    font: game_assets.ui_font.clone(),
+                                    font_size: 20.0,
+                                    color: Color::rgb(0.7, 0.7, 0.7),
+                                },
+                            ));
+                        });
+                }
+            });
+            parent
+                .spawn((ToolWearButton, ButtonBundle {
+                    style: Style {
+                        width: Val::Px(250.0),
+                        height: Val::Px(50.0),
+                        border: UiRect::all(Val::Px(5.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        margin: UiRect::top(Val::Px(30.0)),
+                        ..default()
+                    },
+                    border_color: BorderColor(Color::BLACK),
+                    background_color: NORMAL_BUTTON.into(),
+                    ..default()
+                }))
+                .with_children(|parent| {
+                    parent.spawn((ToolWearLabel, TextBundle::from_section(
+                        tool_wear_text(save.tool_wear),
+                        TextStyle {
+                            font: game_assets.ui_font.clone(),
+                            font_size: 24.0,
+                            color: Color::rgb(0.9, 0.9, 0.9),
+                        },
+                    )));
+                });
+        });
+}
+
+fn hud_setup(mut commands: Commands, game_assets: Res<GameAssets>) {
     // Spawn the parent node that will hold the row of buttons
     commands.spawn(NodeBundle {
         style: Style {
@@ -227,6 +468,9 @@ fn hud_setup(mut commands: Commands, assets: Res<AssetServer>) {
             ("U2", 2),
             ("U3", 3),
             ("U4", 4),
+            ("Wall", 5),
+            ("Hoe+", 6),
+            ("Fix", 7),
         ];
 
         for (label, index) in buttons.iter() {
@@ -256,18 +500,24 @@ fn hud_setup(mut commands: Commands, assets: Res<AssetServer>) {
                 2 => { button.insert(HUDButtonAction::Unit2); }
                 3 => { button.insert(HUDButtonAction::Unit3); }
                 4 => { button.insert(HUDButtonAction::Unit4); }
+                5 => { button.insert(HUDButtonAction::Wall); }
+                6 => { button.insert(HUDButtonAction::UpgradeHoe); }
+                7 => { button.insert(HUDButtonAction::RepairHoe); }
                 _ => {}
             }
 
             button.with_children(|parent| {
-                parent.spawn(TextBundle::from_section(
+                let mut text = parent.spawn(TextBundle::from_section(
                     *label,
                     TextStyle {
-                        font: assets.load("fonts/BebasNeue-Regular.ttf"),
+                        font: game_assets.ui_font.clone(),
                         font_size: 24.0,
                         color: Color::rgb(0.9, 0.9, 0.9),
                     },
                 ));
+                if *index == 7 {
+                    text.insert(DurabilityLabel);
+                }
             });
         }
     });
@@ -278,7 +528,7 @@ fn hud_setup(mut commands: Commands, assets: Res<AssetServer>) {
             width: Val::Percent(100.0),
             height: Val::Percent(100.0),
             position_type: PositionType::Absolute, // Position the node absolutely
-            right: Val::Px(-RESOLUTION_X + 140.), // Align the right edge with the screen's right edge
+            right: Val::Px(-RESOLUTION_X + 280.), // Align the right edge with the screen's right edge
             bottom: Val::Px(-RESOLUTION_Y + 65.), // Align the bottom edge with the screen's bottom edge
             ..default()
         },
@@ -287,6 +537,8 @@ fn hud_setup(mut commands: Commands, assets: Res<AssetServer>) {
         let buttons = [
             ("Music", 0),
             ("SFX", 1),
+            ("1x", 2),
+            ("Dusk", 3),
         ];
 
         for (label, index) in buttons.iter() {
@@ -313,18 +565,23 @@ fn hud_setup(mut commands: Commands, assets: Res<AssetServer>) {
             match index {
                 0 => { button.insert(HUDButtonAction::Music); }
                 1 => { button.insert(HUDButtonAction::SoundEffects); }
+                2 => { button.insert(HUDButtonAction::Speed); }
+                3 => { button.insert(HUDButtonAction::ReadyForNight); }
                 _ => {}
             }
 
             button.with_children(|parent| {
-                parent.spawn(TextBundle::from_section(
+                let mut text = parent.spawn(TextBundle::from_section(
                     *label,
                     TextStyle {
-                        font: assets.load("fonts/BebasNeue-Regular.ttf"),
+                        font: game_assets.ui_font.clone(),
                         font_size: 24.0,
                         color: Color::rgb(0.9, 0.9, 0.9),
                     },
                 ));
+                if *index == 2 {
+                    text.insert(SpeedLabel);
+                }
             });
         }
     });
@@ -375,12 +632,12 @@ fn hud_setup(mut commands: Commands, assets: Res<AssetServer>) {
                     },
                     ..default()
                 },
-                UiImage::new(assets.load("images/Hoe3.png")),
+                UiImage::new(game_assets.resource.clone()),
             ));
             parent.spawn((ResourceCounter, TextBundle::from_section(
                 "1",
                 TextStyle {
-                    font: assets.load("fonts/BebasNeue-Regular.ttf"),
+                    font: game_assets.ui_font.clone(),
                     font_size: 24.0,
                     color: Color::rgb(0.9, 0.9, 0.9),
                 },
@@ -389,6 +646,7 @@ fn hud_setup(mut commands: Commands, assets: Res<AssetServer>) {
     });
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn hud_update (
     mut tiller_interact_query: Query<(
         &Interaction,
@@ -398,8 +656,27 @@ fn hud_update (
         &HUDButtonAction
     ), (Changed<Interaction>, With<HUDButtonAction>)>,
     mut player_tool: Query<&mut PlayerTool, With<Player>>,
-    mut resource_text_query: Query<&mut Text, With<ResourceCounter>>,
+    stockpile: Res<Stockpile>,
+    mut counter_text: Query<&mut Text, (With<ResourceCounter>, Without<SpeedLabel>)>,
+    mut speed_text: Query<&mut Text, (With<SpeedLabel>, Without<ResourceCounter>)>,
+    virtual_time: Res<Time<Virtual>>,
+    mut ev_controls: EventWriter<TimeControlEvent>,
+    mut sfx_settings: ResMut<SfxSettings>,
+    mut ev_shop: EventWriter<ToolShopEvent>,
+    playback: Option<Res<ReplayPlayback>>,
 ) {
+    if stockpile.is_changed() {
+        for mut text in counter_text.iter_mut() {
+            text.sections[0].value = stockpile.0.to_string();
+        }
+    }
+    for mut text in speed_text.iter_mut() {
+        let label = format!("{}x", virtual_time.relative_speed());
+        if text.sections[0].value != label {
+            text.sections[0].value = label;
+        }
+    }
+
     // Handle Main 5 Buttons
     for (interaction, mut color, mut border_color, _children, button_action) in tiller_interact_query.iter_mut() {
         match *interaction {
@@ -408,14 +685,26 @@ fn hud_update (
                 border_color.0 = Color::RED;
                 // Logic
 
+                match button_action {
+                    HUDButtonAction::Speed => { ev_controls.send(TimeControlEvent::CycleSpeed); }
+                    // A replay already knows when the player skipped ahead
+                    HUDButtonAction::ReadyForNight if playback.is_none() => { ev_controls.send(TimeControlEvent::ReadyForNight); }
+                    HUDButtonAction::SoundEffects => { sfx_settings.muted = !sfx_settings.muted; }
+                    HUDButtonAction::UpgradeHoe if playback.is_none() => { ev_shop.send(ToolShopEvent::UpgradeHoe); }
+                    HUDButtonAction::RepairHoe if playback.is_none() => { ev_shop.send(ToolShopEvent::RepairHoe); }
+                    _ => {}
+                }
+
                 if let Ok(mut tool) = player_tool.get_single_mut() {
                     *tool = match button_action {
                         HUDButtonAction::Tiller => PlayerTool::Tiller,
                         HUDButtonAction::Unit1 => PlayerTool::Planter,
                         HUDButtonAction::Unit2 => PlayerTool::Planter,
-                        HUDButtonAction::Unit3 => PlayerTool::Planter,
-                        HUDButtonAction::Unit4 => PlayerTool::Planter,
-                        _ => tool.clone(),
+                        HUDButtonAction::Unit3 => PlayerTool::Build(Buildable::Torch),
+                        HUDButtonAction::Unit4 => PlayerTool::Build(Buildable::Lamp),
+                        // Pressing it again goes up a tier
+                        HUDButtonAction::Wall => player::next_wall(*tool),
+                        _ => *tool,
                     }
                 }
             }
@@ -431,6 +720,17 @@ fn hud_update (
     }
 }
 
+/// Uses left on the hoe, or just "Fix" when tools don't wear
+fn durability_label(tools: Res<Tools>, mut text: Query<&mut Text, With<DurabilityLabel>>) {
+    if !tools.is_changed() {
+        return;
+    }
+    let label = if tools.wear { tools.durability.to_string() } else { "Fix".to_string() };
+    for mut text in text.iter_mut() {
+        text.sections[0].value = label.clone();
+    }
+}
+
 fn cleanup(mut commands: Commands, query: Query<Entity, With<Node>>, cams: Query<Entity, With<Camera>>) {
     for entity in query.iter() {
         commands.entity(entity).despawn();
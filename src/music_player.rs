@@ -1,75 +1,297 @@
-use bevy_kira_audio::prelude::*;
-use bevy::{prelude::*, utils::tracing::instrument};
+use bevy_kira_audio::{prelude::*, AudioSource};
+use bevy::{prelude::*, utils::HashMap};
 use std::time::Duration;
 
-use crate::{game::*};
+use crate::{
+    day_night::{DayNightCycle, DayPhase, PhaseChangedEvent},
+    enemies::{CorePaths, Enemy},
+    loading::GameAssets,
+    map,
+    the_core::CoreHealth,
+    weather::{Weather, WeatherKind},
+};
 
+/// Tempo the soundtrack is written at, crossfades wait for the next beat
+const BPM: f64 = 120.;
+/// Volume of whichever track is playing
+const MUSIC_VOLUME: f64 = 0.5;
+/// Intensity where the night track takes over, even in daylight
+const TENSE_INTENSITY: f32 = 0.6;
+/// How fast intensity catches up with what's going on, per second
+const INTENSITY_RATE: f32 = 0.25;
+/// Steps from the core where an enemy starts bringing the percussion in
+const APPROACH_STEPS: f32 = 30.;
 
+/// The main tracks, only one plays at a time
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Track {
+    Day,
+    Night,
+    GameOver,
+}
+
+/// Short one shots that come in over the music on the next beat
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Stinger {
+    WaveStart,
+    CoreDamage,
+}
+
+impl Stinger {
+    pub const ALL: [Stinger; 2] = [Stinger::WaveStart, Stinger::CoreDamage];
+
+    /// Loaded with the rest of `GameAssets`
+    pub fn path(&self) -> &'static str {
+        match self {
+            Stinger::WaveStart => "music/stinger_wave.wav",
+            Stinger::CoreDamage => "music/stinger_core.wav",
+        }
+    }
+}
+
+/// Play a stinger over the music
+#[derive(Event, Clone, Copy, Debug)]
+pub struct StingerEvent(pub Stinger);
+
+/// How heated the game is right now, drives the music
+#[derive(Resource, Default)]
+pub struct GameIntensity {
+    /// 0.0 - 1.0, eased towards the target so the music doesn't lurch
+    pub level: f32,
+    /// Extra intensity from damage to the core, 0.0 - 1.0
+    pub threat: f32,
+    /// How close the nearest enemy is to the core, 0.0 - 1.0. Sets how loud the percussion is.
+    pub approach: f32,
+    /// The core is gone, play the game over track
+    pub game_over: bool,
+}
+
+/// Keeps track of what's playing and what's about to
 #[derive(Resource)]
-pub struct DayMusicInstanceHandle(Handle<AudioInstance>);
+pub struct MusicDirector {
+    tracks: HashMap<Track, Handle<AudioInstance>>,
+    /// Drums that loop alongside the tracks and come up as enemies close in
+    percussion: Handle<AudioInstance>,
+    percussion_volume: f64,
+    current: Track,
+    /// Track waiting for the next beat to crossfade in
+    pending: Option<Track>,
+    /// Beat the current track was on last frame
+    last_beat: Option<u64>,
+}
 
+/// The menu theme while it's playing
 #[derive(Resource)]
-pub struct NightMusicInstanceHandle(Handle<AudioInstance>);
-
-pub fn start_day_music(mut commands: Commands, asset_server: Res<AssetServer>, audio: Res<Audio>) {
-    
-    // Play the day music
-    let handle = audio.play(asset_server.load("music/SolFarmer_4_Track_2.ogg"))
-    // Fade-in with a dynamic easing
-    .fade_in(AudioTween::new(Duration::from_secs(1), AudioEasing::OutPowi(2)))
-    // Play at half volume
-    .with_volume(0.5)
-    // play the track reversed
-    .looped().handle();
-    // Add the resource
-    commands.insert_resource(DayMusicInstanceHandle(handle));
-}
-
-pub fn start_night_music(mut commands: Commands, asset_server: Res<AssetServer>, audio: Res<Audio>) {
-    // Play the day music
-    let handle = audio.play(asset_server.load("music/SolFarmer_4_Track.ogg"))
-    // Fade-in with a dynamic easing
-    .fade_in(AudioTween::new(Duration::from_secs(2), AudioEasing::OutPowi(2)))
-    // Play at half volume
-    .with_volume(0.0)
-    // play the track reversed
-    .looped().handle();
-    // Add the resource
-    commands.insert_resource(NightMusicInstanceHandle(handle));
-}
-
-pub fn instance_control(
-    day_handle: Res<DayMusicInstanceHandle>,
-    night_handle: Res<NightMusicInstanceHandle>,
+pub struct MenuMusic(Handle<AudioInstance>);
+
+/// Play the day track over the menu, unless it's still going from the last menu screen
+pub fn start_menu_music(mut commands: Commands, game_assets: Res<GameAssets>, menu_music: Option<Res<MenuMusic>>, audio: Res<Audio>) {
+    if menu_music.is_some() {
+        return;
+    }
+    let handle = audio.play(game_assets.day_music.clone())
+        .fade_in(AudioTween::new(Duration::from_secs(1), AudioEasing::OutPowi(2)))
+        .with_volume(MUSIC_VOLUME)
+        .looped()
+        .handle();
+    commands.insert_resource(MenuMusic(handle));
+}
+
+/// Fade the menu theme out as the game starts
+pub fn stop_menu_music(mut commands: Commands, menu_music: Option<Res<MenuMusic>>, mut audio_instances: ResMut<Assets<AudioInstance>>) {
+    let Some(menu_music) = menu_music else { return; };
+    if let Some(instance) = audio_instances.get_mut(&menu_music.0) {
+        instance.stop(AudioTween::new(Duration::from_secs(1), AudioEasing::OutPowi(2)));
+    }
+    commands.remove_resource::<MenuMusic>();
+}
+
+/// The track that belongs to a time of day
+fn phase_track(phase: DayPhase) -> Track {
+    match phase {
+        DayPhase::Dawn | DayPhase::Day => Track::Day,
+        DayPhase::Dusk | DayPhase::Night => Track::Night,
+    }
+}
+
+/// Start every track looping together, muted apart from the one for the current
+/// phase, so they stay in time with each other
+pub fn start_music(
+    mut commands: Commands,
+    game_assets: Res<GameAssets>,
+    cycle: Res<DayNightCycle>,
+    audio: Res<Audio>,
+) {
+    let current = phase_track(cycle.phase);
+    let play = |track: Track, source: &Handle<AudioSource>| {
+        audio.play(source.clone())
+            .fade_in(AudioTween::new(Duration::from_secs(1), AudioEasing::OutPowi(2)))
+            .with_volume(if track == current { MUSIC_VOLUME } else { 0. })
+            .looped()
+            .handle()
+    };
+
+    let mut tracks = HashMap::new();
+    tracks.insert(Track::Day, play(Track::Day, &game_assets.day_music));
+    tracks.insert(Track::Night, play(Track::Night, &game_assets.night_music));
+    tracks.insert(Track::GameOver, play(Track::GameOver, &game_assets.game_over_music));
+    let percussion = audio.play(game_assets.percussion_stem.clone()).with_volume(0.).looped().handle();
+
+    commands.insert_resource(MusicDirector {
+        tracks,
+        percussion,
+        percussion_volume: 0.,
+        current,
+        pending: None,
+        last_beat: None,
+    });
+}
+
+/// Fade out everything the game was playing once we leave it
+pub fn stop_music(mut commands: Commands, director: Option<Res<MusicDirector>>, mut audio_instances: ResMut<Assets<AudioInstance>>) {
+    let Some(director) = director else { return; };
+    for handle in director.tracks.values().chain([&director.percussion]) {
+        if let Some(instance) = audio_instances.get_mut(handle) {
+            instance.stop(AudioTween::new(Duration::from_secs(1), AudioEasing::OutPowi(2)));
+        }
+    }
+    commands.remove_resource::<MusicDirector>();
+}
+
+/// The nearer an enemy is to the core, going by the way it has to walk, the louder the drums
+pub fn enemy_approach(
+    paths: Res<CorePaths>,
+    enemies: Query<&Transform, With<Enemy>>,
+    mut intensity: ResMut<GameIntensity>,
+) {
+    let nearest = enemies.iter()
+        .filter_map(|transform| paths.0.steps(map::get_tile(transform.translation.x, transform.translation.y)))
+        .min();
+    intensity.approach = nearest.map_or(0., |steps| (1. - steps as f32 / APPROACH_STEPS).clamp(0., 1.));
+}
+
+/// Work out how intense things are from the time of day, the weather, enemies
+/// closing in and any threat to the core
+pub fn update_intensity(
+    time: Res<Time>,
+    cycle: Res<DayNightCycle>,
+    weather: Res<Weather>,
+    mut intensity: ResMut<GameIntensity>,
+) {
+    let phase = match cycle.phase {
+        DayPhase::Dawn => 0.,
+        DayPhase::Day => 0.1,
+        DayPhase::Dusk => 0.3,
+        DayPhase::Night => 0.5,
+    };
+    let weather = if weather.kind == WeatherKind::Clear { 0. } else { 0.1 };
+    let target = (phase + weather + intensity.approach * 0.3 + intensity.threat.clamp(0., 1.)).min(1.);
+
+    let step = INTENSITY_RATE * time.delta_seconds();
+    intensity.level += (target - intensity.level).clamp(-step, step);
+}
+
+/// Wave start and core damage get a stinger
+pub fn queue_stingers(
+    core: Res<CoreHealth>,
+    mut last: Local<Option<f32>>,
+    mut ev_phase: EventReader<PhaseChangedEvent>,
+    mut ev_stinger: EventWriter<StingerEvent>,
+) {
+    for ev in ev_phase.read() {
+        if ev.to == DayPhase::Night {
+            ev_stinger.send(StingerEvent(Stinger::WaveStart));
+        }
+    }
+    if last.is_some_and(|last| core.0 < last) {
+        ev_stinger.send(StingerEvent(Stinger::CoreDamage));
+    }
+    *last = Some(core.0);
+}
+
+/// Pick the track for the moment and crossfade to it on the beat, bring the
+/// percussion in as enemies close in, and play stingers on the beat too
+#[allow(clippy::too_many_arguments)]
+pub fn direct_music(
+    cycle: Res<DayNightCycle>,
+    intensity: Res<GameIntensity>,
+    game_assets: Res<GameAssets>,
+    audio: Res<Audio>,
+    mut director: ResMut<MusicDirector>,
     mut audio_instances: ResMut<Assets<AudioInstance>>,
-    mut ev_dawn: EventReader<DawnStartEvent>,
-    mut ev_dusk: EventReader<DuskStartEvent>,
+    mut ev_stinger: EventReader<StingerEvent>,
+    mut stingers: Local<Vec<Stinger>>,
 ) {
+    let wanted = if intensity.game_over {
+        Track::GameOver
+    } else if intensity.level >= TENSE_INTENSITY {
+        Track::Night
+    } else {
+        phase_track(cycle.phase)
+    };
+    director.pending = (wanted != director.current).then_some(wanted);
+    for StingerEvent(stinger) in ev_stinger.read() {
+        if !stingers.contains(stinger) {
+            stingers.push(*stinger);
+        }
+    }
 
-    // Handle Dawn
-    for _ev in ev_dawn.read() {
-        // "Lerp" day music volume up to 0.5
-        if let Some(instance) = audio_instances.get_mut(&day_handle.0) {
-            instance.set_volume(0.5, AudioTween::new(Duration::new(3, 0), AudioEasing::InPowf(2.)));
-            // Reset Song
-            instance.seek_to(0.0);
+    let beat = director.tracks.get(&director.current)
+        .and_then(|handle| audio_instances.get(handle))
+        .and_then(|instance| instance.state().position())
+        .map(|position| (position * BPM / 60.) as u64);
+    // Nothing playing means there's no beat to wait for
+    let on_beat = match (beat, director.last_beat) {
+        (Some(beat), Some(last)) => beat != last,
+        (None, _) => true,
+        _ => false,
+    };
+    director.last_beat = beat;
+    if on_beat {
+        if let Some(next) = director.pending.take() {
+            crossfade(&director.tracks, director.current, next, &mut audio_instances);
+            director.current = next;
+            director.last_beat = None;
         }
-        // "Lerp" night music volume down to 0.0
-        if let Some(instance) = audio_instances.get_mut(&night_handle.0) {
-            instance.set_volume(0.0, AudioTween::new(Duration::new(3, 0), AudioEasing::OutPowf(2.)));
+        for stinger in stingers.drain(..) {
+            if let Some(source) = game_assets.music_stingers.get(&stinger) {
+                audio.play(source.clone()).with_volume(MUSIC_VOLUME);
+            }
         }
     }
-    // Handle Dawn
-    for _ev in ev_dusk.read() {
-        // "Lerp" day music volume down to 0.0
-        if let Some(instance) = audio_instances.get_mut(&day_handle.0) {
-            instance.set_volume(0.0, AudioTween::new(Duration::new(3, 0), AudioEasing::OutPowf(2.)));
+
+    // Drums never play over the game over track
+    let percussion_volume = if director.current == Track::GameOver { 0. } else { MUSIC_VOLUME * intensity.approach as f64 };
+    if (percussion_volume - director.percussion_volume).abs() > 0.02 {
+        if let Some(instance) = audio_instances.get_mut(&director.percussion) {
+            instance.set_volume(percussion_volume, AudioTween::new(Duration::from_millis(500), AudioEasing::Linear));
         }
-        // "Lerp" night music volume up to 0.5
-        if let Some(instance) = audio_instances.get_mut(&night_handle.0) {
-            instance.set_volume(0.5, AudioTween::new(Duration::new(3, 0), AudioEasing::InPowf(2.)));
-            // Reset Song
-            instance.seek_to(0.0);
+        director.percussion_volume = percussion_volume;
+    }
+}
+
+/// Fade one track out and the next in. They've been looping together all along, so they stay in time.
+fn crossfade(tracks: &HashMap<Track, Handle<AudioInstance>>, from: Track, to: Track, audio_instances: &mut Assets<AudioInstance>) {
+    if let Some(instance) = tracks.get(&from).and_then(|handle| audio_instances.get_mut(handle)) {
+        instance.set_volume(0.0, AudioTween::new(Duration::new(3, 0), AudioEasing::OutPowf(2.)));
+    }
+    if let Some(instance) = tracks.get(&to).and_then(|handle| audio_instances.get_mut(handle)) {
+        instance.set_volume(MUSIC_VOLUME, AudioTween::new(Duration::new(3, 0), AudioEasing::InPowf(2.)));
+    }
+}
+
+/// Weather sets the tempo, storms drag the music down
+pub fn weather_music(
+    weather: Res<Weather>,
+    director: Res<MusicDirector>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+) {
+    if !weather.is_changed() {
+        return;
+    }
+    for handle in director.tracks.values().chain([&director.percussion]) {
+        if let Some(instance) = audio_instances.get_mut(handle) {
+            instance.set_playback_rate(weather.kind.music_rate(), AudioTween::new(Duration::new(2, 0), AudioEasing::InOutPowi(2)));
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,179 @@
+//! Getting around the map on foot. Only walls get in the way for now.
+
+use std::collections::VecDeque;
+
+use bevy::utils::HashSet;
+
+/// Whether enemies can come onto the map at `tile`. They come over the top, left and right
+/// edges, the homestead backs onto the bottom one.
+pub fn is_entry(width: i32, height: i32, (x, y): (i32, i32)) -> bool {
+    x == 0 || x == width - 1 || y == height - 1
+}
+
+/// Whether something at `from` could walk to an entry point of a `width` by `height` map,
+/// going around blocked tiles one step up, down, left or right at a time
+pub fn reaches_entry(width: i32, height: i32, from: (i32, i32), blocked: impl Fn((i32, i32)) -> bool) -> bool {
+    if blocked(from) {
+        return false;
+    }
+    let mut seen = HashSet::new();
+    let mut open = VecDeque::from([from]);
+    seen.insert(from);
+    while let Some((x, y)) = open.pop_front() {
+        if is_entry(width, height, (x, y)) {
+            return true;
+        }
+        for next in neighbours((x, y)) {
+            let on_map = next.0 >= 0 && next.1 >= 0 && next.0 < width && next.1 < height;
+            if on_map && !blocked(next) && seen.insert(next) {
+                open.push_back(next);
+            }
+        }
+    }
+    false
+}
+
+/// Whether blocking `tile` as well would leave nothing able to walk in from an entry point to the core
+pub fn seals_core(width: i32, height: i32, core: (i32, i32), tile: (i32, i32), blocked: impl Fn((i32, i32)) -> bool) -> bool {
+    !reaches_entry(width, height, core, |next| next == tile || blocked(next))
+}
+
+/// Tiles enemies can come onto a `width` by `height` map at
+pub fn entries(width: i32, height: i32) -> impl Iterator<Item = (i32, i32)> {
+    (0..width).flat_map(move |x| (0..height).map(move |y| (x, y))).filter(move |tile| is_entry(width, height, *tile))
+}
+
+/// Steps from every tile to one target tile, so everything heading there shares one search
+#[derive(Clone, Default, Debug)]
+pub struct FlowField {
+    width: i32,
+    height: i32,
+    target: (i32, i32),
+    /// None where the target can't be reached from
+    steps: Vec<Option<u32>>,
+}
+
+impl FlowField {
+    pub fn new(width: i32, height: i32, target: (i32, i32), blocked: impl Fn((i32, i32)) -> bool) -> FlowField {
+        let mut field = FlowField { width, height, target, steps: vec![None; (width * height).max(0) as usize] };
+        let Some(i) = field.index(target) else { return field; };
+        field.steps[i] = Some(0);
+        let mut open = VecDeque::from([target]);
+        while let Some(tile) = open.pop_front() {
+            let here = field.steps(tile).unwrap_or_default();
+            for next in neighbours(tile) {
+                let Some(i) = field.index(next) else { continue; };
+                if field.steps[i].is_none() && !blocked(next) {
+                    field.steps[i] = Some(here + 1);
+                    open.push_back(next);
+                }
+            }
+        }
+        field
+    }
+
+    fn index(&self, (x, y): (i32, i32)) -> Option<usize> {
+        (x >= 0 && y >= 0 && x < self.width && y < self.height).then(|| (y * self.width + x) as usize)
+    }
+
+    /// Steps from a tile to the target
+    pub fn steps(&self, tile: (i32, i32)) -> Option<u32> {
+        self.index(tile).and_then(|i| self.steps[i])
+    }
+
+    /// The neighbour a step closer to the target. Something stood on a blocked tile
+    /// still gets a way off it. None at the target, or when it's out of reach.
+    pub fn next(&self, tile: (i32, i32)) -> Option<(i32, i32)> {
+        let here = self.steps(tile).unwrap_or(u32::MAX);
+        // Of the equally short ways, head straight at the target rather than along an edge
+        let off_line = |(x, y): (i32, i32)| (x - self.target.0).pow(2) + (y - self.target.1).pow(2);
+        neighbours(tile)
+            .into_iter()
+            .filter_map(|next| Some((self.steps(next)?, next)))
+            .filter(|(steps, _)| *steps < here)
+            .min_by_key(|(steps, next)| (*steps, off_line(*next)))
+            .map(|(_, next)| next)
+    }
+
+    /// Every tile from `from` to the target, both ends included
+    pub fn path(&self, from: (i32, i32)) -> Vec<(i32, i32)> {
+        let mut path = vec![from];
+        while let Some(next) = self.next(*path.last().unwrap()) {
+            path.push(next);
+        }
+        path
+    }
+}
+
+fn neighbours((x, y): (i32, i32)) -> [(i32, i32); 4] {
+    [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tiles around the core, a square ring `radius` tiles out
+    fn ring(core: (i32, i32), radius: i32) -> Vec<(i32, i32)> {
+        let (x, y) = core;
+        (-radius..=radius)
+            .flat_map(|d| [(x + d, y - radius), (x + d, y + radius), (x - radius, y + d), (x + radius, y + d)])
+            .collect()
+    }
+
+    #[test]
+    fn open_map_reaches_an_entry() {
+        assert!(reaches_entry(10, 10, (5, 5), |_| false));
+        assert!(!seals_core(10, 10, (5, 5), (4, 5), |_| false));
+    }
+
+    #[test]
+    fn last_gap_in_a_ring_seals_the_core() {
+        let gap = (5, 3);
+        let rest: Vec<_> = ring((5, 5), 2).into_iter().filter(|tile| *tile != gap).collect();
+        assert!(seals_core(10, 10, (5, 5), gap, |tile| rest.contains(&tile)));
+        // Any other tile leaves the gap open
+        assert!(!seals_core(10, 10, (5, 5), (0, 0), |tile| rest.contains(&tile)));
+    }
+
+    #[test]
+    fn walling_the_core_itself_seals_it() {
+        assert!(seals_core(10, 10, (5, 5), (5, 5), |_| false));
+    }
+
+    #[test]
+    fn diagonal_gaps_dont_count() {
+        // Only the corners of the ring are open, and nothing squeezes between walls diagonally
+        let walls: Vec<_> = ring((5, 5), 1).into_iter().filter(|(x, y)| *x == 5 || *y == 5).collect();
+        assert!(!reaches_entry(10, 10, (5, 5), |tile| walls.contains(&tile)));
+    }
+
+    #[test]
+    fn bottom_edge_isnt_a_way_in() {
+        // Core right against the bottom edge, walled in on every other side
+        let walls = [(0, 0), (2, 0), (0, 1), (1, 1), (2, 1)];
+        assert!(!reaches_entry(5, 5, (1, 0), |tile| walls.contains(&tile)));
+        // The side edges are, all the way down
+        assert!(reaches_entry(5, 5, (1, 0), |tile| tile == (2, 0)));
+    }
+
+    #[test]
+    fn flow_goes_around_walls() {
+        // A wall across the middle with a gap at the right end
+        let walls: Vec<_> = (0..4).map(|x| (x, 2)).collect();
+        let field = FlowField::new(5, 5, (0, 0), |tile| walls.contains(&tile));
+        assert_eq!(field.steps((0, 4)), Some(12));
+        let path = field.path((0, 4));
+        assert_eq!(path.len(), 13);
+        assert!(path.contains(&(4, 2)));
+        assert_eq!(path.last(), Some(&(0, 0)));
+        assert_eq!(field.next((0, 0)), None);
+    }
+
+    #[test]
+    fn flow_leads_off_a_walled_tile() {
+        let field = FlowField::new(5, 5, (0, 0), |tile| tile == (2, 2));
+        assert_eq!(field.steps((2, 2)), None);
+        assert!(field.next((2, 2)).is_some_and(|next| field.steps(next) == Some(3)));
+    }
+}
@@ -1,7 +1,10 @@
+use std::collections::VecDeque;
+
 use bevy::prelude::*;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 
-use crate::{game, map::{self, TileBundle, TileState}, spriteanims};
+use crate::{bases::{self, Bases}, building::{self, Buildable, Building, Buildings}, crops::{Crops, HARVEST_YIELD}, day_night::DayNightCycle, effects::{EffectKind, SpawnEffectEvent}, game::{self, Stockpile}, lighting::Lit, loading::GameAssets, map::{self, MapSize}, run::{ProfileTraits, Run, RunRng}, sfx::{Sfx, SfxEvent}, spriteanims, tools::Tools, walls::{self, WallTier}, weather::Weather};
 
 #[derive(Component)]
 pub struct Player;
@@ -16,16 +19,43 @@ const MOVE_SPEED: f32 = 90.;
 #[derive(Component)]
 pub struct PlayerAttack (pub Timer);
 
+/// Per run stats, from the picked character profile
+#[derive(Component)]
+pub struct PlayerStats {
+    pub move_speed: f32,
+    /// Tool reach, in tiles
+    pub reach: f32,
+    /// Seconds between swings with the first hoe, better hoes swing faster
+    pub swing_cooldown: f32,
+    /// Seconds the player stands still for each tile worked, before the hoe's tier
+    pub tool_use_time: f32,
+}
+
 const ATTACK_COOLDOWN: f32 = 0.4;
+/// How many tiles away the player can use a tool on
+const TOOL_REACH: f32 = 3.;
+/// Seconds the player stands still while using a tool
+const TOOL_USE_TIME: f32 = 0.3;
+/// Highlight tint for tiles out of reach
+const OUT_OF_REACH: Color = Color::rgba(1., 0.2, 0.2, 0.8);
+/// Highlight tint for tiles in a drag
+const DRAG_TINT: Color = Color::rgba(1., 1., 1., 0.6);
+/// Longest side of a drag rectangle, in tiles
+const MAX_DRAG: i32 = 12;
+
+/// Counts down while the player is busy using a tool, they can't move until it's done
+#[derive(Component)]
+pub struct ToolUse(pub Timer);
 
 #[derive(Component)]
 pub struct Hoe;
 
-#[derive(Component, Clone)]
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum PlayerTool {
     Tiller,
     Planter,
-    Rake
+    Rake,
+    Build(Buildable),
 }
 
 
@@ -34,41 +64,60 @@ pub struct PlayerBundle {
     sprite_anim: spriteanims::HumanAnimator,
     ply:  Player,
     attack: PlayerAttack,
-    tool: PlayerTool
+    tool_use: ToolUse,
+    stats: PlayerStats,
+    tool: PlayerTool,
+    lit: Lit,
 }
 
 impl PlayerBundle {
-    pub fn new(anim: spriteanims::HumanAnimator) -> PlayerBundle {
+    pub fn new(anim: spriteanims::HumanAnimator, traits: ProfileTraits) -> PlayerBundle {
         PlayerBundle {
             sprite_anim: anim,
             ply: Player,
-            attack: PlayerAttack(Timer::from_seconds(ATTACK_COOLDOWN, TimerMode::Once)),
-            tool: PlayerTool::Planter
+            attack: PlayerAttack(Timer::from_seconds(ATTACK_COOLDOWN * traits.swing_cooldown, TimerMode::Once)),
+            tool_use: ToolUse(Timer::from_seconds(0., TimerMode::Once)),
+            stats: PlayerStats {
+                move_speed: MOVE_SPEED * traits.move_speed,
+                reach: TOOL_REACH,
+                swing_cooldown: ATTACK_COOLDOWN * traits.swing_cooldown,
+                tool_use_time: TOOL_USE_TIME * traits.swing_cooldown,
+            },
+            tool: PlayerTool::Planter,
+            lit: Lit,
         }
     }
 }   
 
 
 
-pub fn setup(mut commands: Commands, assets: Res<AssetServer>){
-    let spawn = map::get_world(SPAWN_X, SPAWN_Y);
+pub fn setup(mut commands: Commands, game_assets: Res<GameAssets>, sheets: Res<Assets<spriteanims::AnimationSheet>>, map_size: Res<MapSize>, run: Res<Run>){
+    let (spawn_x, spawn_y) = map_size.home(SPAWN_X, SPAWN_Y);
+    let spawn = map::get_world(spawn_x, spawn_y);
+
+    let sheet_handle = game_assets.character_sheets[run.profile.sheet()].clone();
+    let Some(sheet) = sheets.get(&sheet_handle) else { error!("Player sheet isn't loaded"); return; };
 
     commands.spawn( 
         PlayerBundle::new(spriteanims::HumanAnimator::new(
-            assets.load("entity/human_profile/ed_sheet.png"), spriteanims::HumanAnimState::FaceDown, Vec3 {x: spawn.0, y: spawn.1, z :1.0}))
+            sheet_handle.clone(), sheet, Vec3 {x: spawn.0, y: spawn.1, z :1.0}), run.profile.traits())
     ).with_children(|parent| {
         parent.spawn((SpriteBundle {
-            texture: assets.load("images/hoe1.png"),
+            texture: game_assets.hoes[0].clone(),
             transform: Transform::from_xyz(-8., 8., 5.),
             ..default()
         },
         Hoe,
+        Lit,
     ));
     });
 
+    commands.insert_resource(WorkQueue::default());
+    commands.insert_resource(DragSelect::default());
+
     commands.spawn( (
         SpriteBundle {
-            texture: assets.load("tiles/highlight.png"),
+            texture: game_assets.highlight.clone(),
             transform: Transform::from_xyz(0., 0., 1.),
             ..default()
         },
@@ -77,38 +126,64 @@ pub fn setup(mut commands: Commands, assets: Res<AssetServer>){
 }
 
 /// Move the player around 
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 pub fn player_input(
-    mut query: Query<(Entity, &mut Transform, &mut PlayerAttack, &mut Sprite, &mut spriteanims::HumanAnimState, &mut PlayerTool), With<Player>>,
+    mut query: Query<(&mut Transform, &mut PlayerAttack, &mut ToolUse, &PlayerStats, &mut spriteanims::Facing, &mut spriteanims::Moving, &mut spriteanims::SpriteAnimator, &mut PlayerTool), With<Player>>,
     keycode: Res<ButtonInput<KeyCode>>,
-    mouse: Res<game::MyWorldCoords>,
-    mouse_input: Res<ButtonInput<MouseButton>>,
+    map_size: Res<MapSize>,
+    weather: Res<Weather>,
+    buildings: Res<Buildings>,
+    placed: Query<&Building>,
+    mut ev_swing: EventWriter<SwingEvent>,
     time: Res<Time>
 )
 {
-    if let Ok((_e, mut transform, mut player_attack, mut sprite, mut state, mut tool )) = query.get_single_mut() {
+    if let Ok((mut transform, mut player_attack, mut tool_use, stats, mut facing, mut moving, mut animator, mut tool )) = query.get_single_mut() {
         // Tick the attack timer
         player_attack.0.tick(time.delta());
-        let move_distance = MOVE_SPEED * time.delta_seconds();
-        sprite.rect = Some(state.getRect());
+        // Planted to the spot while using a tool
+        let busy = !tool_use.0.tick(time.delta()).finished();
+        let move_distance = if busy { 0. } else { stats.move_speed * weather.kind.move_factor() * time.delta_seconds() };
+        let start = transform.translation;
 
         if keycode.pressed(KeyCode::KeyW) {
             
-            *state = spriteanims::HumanAnimState::FaceUp;
+            *facing = spriteanims::Facing::Up;
             transform.translation.y += move_distance;
         }
         if keycode.pressed(KeyCode::KeyS) {
-            *state = spriteanims::HumanAnimState::FaceDown;
+            *facing = spriteanims::Facing::Down;
             transform.translation.y -= move_distance;
         }
         if keycode.pressed(KeyCode::KeyD) {
-            *state = spriteanims::HumanAnimState::FaceRight;
+            *facing = spriteanims::Facing::Right;
             transform.translation.x += move_distance;
         }
         if keycode.pressed(KeyCode::KeyA) {
-            *state = spriteanims::HumanAnimState::FaceLeft;
+            *facing = spriteanims::Facing::Left;
             transform.translation.x -= move_distance;
         }
 
+        // Keep the player on the map
+        let bounds = map_size.world_bounds();
+        transform.translation.x = transform.translation.x.clamp(bounds.min.x, bounds.max.x);
+        transform.translation.y = transform.translation.y.clamp(bounds.min.y, bounds.max.y);
+
+        // Walls stop the player, each way on its own so they can slide along them.
+        // Anyone caught on a tile when a wall goes up can still walk off it.
+        let start_tile = map::get_tile(start.x, start.y);
+        let walled = |x: f32, y: f32| {
+            let tile = map::get_tile(x, y);
+            tile != start_tile && walls::is_wall(&buildings, &placed, tile)
+        };
+        if walled(transform.translation.x, start.y) {
+            transform.translation.x = start.x;
+        }
+        if walled(transform.translation.x, transform.translation.y) {
+            transform.translation.y = start.y;
+        }
+        moving.0 = transform.translation != start;
+
 
         if keycode.just_pressed(KeyCode::Digit1){
             *tool = PlayerTool::Tiller;
@@ -119,36 +194,59 @@ pub fn player_input(
         if keycode.just_pressed(KeyCode::Digit3){
             *tool = PlayerTool::Rake;
         }
+        if keycode.just_pressed(KeyCode::Digit4){
+            *tool = PlayerTool::Build(Buildable::Torch);
+        }
+        if keycode.just_pressed(KeyCode::Digit5){
+            *tool = PlayerTool::Build(Buildable::Lamp);
+        }
+        if keycode.just_pressed(KeyCode::Digit6){
+            *tool = next_wall(*tool);
+        }
 
         // If the player can attack and is trying to attack
         if (keycode.pressed(KeyCode::Space)) && (player_attack.0.finished()) {
             // Reset the attack timer
             player_attack.0.reset();
-        }
-
-        // Handle the placing/removal of the plant units
-        if mouse_input.just_pressed(MouseButton::Left) {
-            // Get the indices of the tile clicked on
-            let (x, y) = map::get_tile(mouse.0.x, mouse.0.y);
-            println!("{}, {}", x, y);
-        }
-        if mouse_input.just_pressed(MouseButton::Right) {
-            
+            animator.restart(spriteanims::clip_name(spriteanims::Action::Attack, *facing));
+            ev_swing.send(SwingEvent(transform.translation.truncate()));
         }
     } 
 }
 
+/// Pick walls, or the next tier up if walls are already picked
+pub fn next_wall(tool: PlayerTool) -> PlayerTool {
+    match tool {
+        PlayerTool::Build(Buildable::Wall(tier)) => PlayerTool::Build(Buildable::Wall(tier.next())),
+        _ => PlayerTool::Build(Buildable::Wall(WallTier::default())),
+    }
+}
+
+/// Whether a tile is close enough to the player to use a tool on
+pub fn in_reach(player: Vec3, stats: &PlayerStats, tile: (i32, i32)) -> bool {
+    map::distance_int_from_point(map::get_tile(player.x, player.y), tile) <= stats.reach
+}
+
+/// Follow the mouse, going red over tiles the player can't reach
+#[allow(clippy::type_complexity)]
 pub fn render_tile_highlight(
     mouse: Res<game::MyWorldCoords>,
-    mut tile_highlight: Query<&mut Transform, With<Mouse>>
+    player_query: Query<(&Transform, &PlayerStats), With<Player>>,
+    mut tile_highlight: Query<(&mut Transform, &mut Sprite), (With<Mouse>, Without<Player>)>
 )
 {
-    if let Ok(mut transform) = tile_highlight.get_single_mut() {
-        let (x, y) = map::get_tile(mouse.0.x, mouse.0.y);
-        let (x, y) = map::get_world(x, y);
+    if let Ok((mut transform, mut sprite)) = tile_highlight.get_single_mut() {
+        let tile = map::get_tile(mouse.0.x, mouse.0.y);
+        let (x, y) = map::get_world(tile.0, tile.1);
 
         transform.translation.x = x;
         transform.translation.y = y;
+
+        let reachable = player_query.get_single().map_or(true, |(player, stats)| in_reach(player.translation, stats, tile));
+        let color = if reachable { Color::WHITE } else { OUT_OF_REACH };
+        if sprite.color != color {
+            sprite.color = color;
+        }
     }
 }
 
@@ -177,7 +275,7 @@ pub fn hoe_swing(
         } else {
             for mut hoe_transform in hoe_query.iter_mut() {
                 // Calculate the offsets using an angle of 0 degrees
-                let angle = 0 as f32;
+                let angle = 0_f32;
                 let offset_x = 16. * (angle + std::f32::consts::PI).cos();
                 let offset_y = 16. * (angle + std::f32::consts::PI).sin();
                 // Keep the hoe at default position
@@ -192,142 +290,256 @@ pub fn hoe_swing(
 
 
 #[derive(Event)]
-pub struct ClickEvent((i32, i32), PlayerTool);
+pub struct ClickEvent(pub (i32, i32), pub PlayerTool);
+
+/// The player swung the hoe from here, hitting whatever's around them
+#[derive(Event, Clone, Copy, PartialEq, Debug)]
+pub struct SwingEvent(pub Vec2);
+
+/// Tiles picked with a click or a drag, worked through one tool use at a time
+#[derive(Resource, Default)]
+pub struct WorkQueue {
+    tool: Option<PlayerTool>,
+    tiles: VecDeque<(i32, i32)>,
+}
+
+/// Corners of the drag in progress
+#[derive(Resource, Default)]
+pub struct DragSelect {
+    start: Option<(i32, i32)>,
+    end: (i32, i32),
+}
+
+impl DragSelect {
+    /// Tiles in the dragged rectangle, capped so a wild drag doesn't cover the map
+    fn tiles(&self) -> impl Iterator<Item = (i32, i32)> {
+        let start = self.start.unwrap_or(self.end);
+        let clamp = |from: i32, to: i32| to.clamp(from - MAX_DRAG + 1, from + MAX_DRAG - 1);
+        let end = (clamp(start.0, self.end.0), clamp(start.1, self.end.1));
+        let (min_x, max_x) = (start.0.min(end.0), start.0.max(end.0));
+        let (min_y, max_y) = (start.1.min(end.1), start.1.max(end.1));
+        (min_x..=max_x).flat_map(move |x| (min_y..=max_y).map(move |y| (x, y)))
+    }
+}
+
+/// Shows a tile in the drag rectangle
+#[derive(Component)]
+pub struct DragPreview;
 
-/// Control tile placement
-pub fn mouse_tile_select(
-    player_query: Query<&PlayerTool, With<Player>>,
+/// Click or drag out a rectangle of tiles to work. Right click drops whatever's left.
+pub fn select_tiles(
+    player_query: Query<(&PlayerTool, &Transform, &PlayerStats), With<Player>>,
     mouse: Res<game::MyWorldCoords>,
     mouse_input: Res<ButtonInput<MouseButton>>,
-    mut click_event: EventWriter<ClickEvent>,
+    mut drag: ResMut<DragSelect>,
+    mut queue: ResMut<WorkQueue>,
 ){
-    let Ok((tool)) = player_query.get_single() else {warn!("No tool"); return;};
+    let Ok((tool, transform, stats)) = player_query.get_single() else { return; };
+    let mouse_tile = map::get_tile(mouse.0.x, mouse.0.y);
+    if mouse_input.just_pressed(MouseButton::Right) {
+        drag.start = None;
+        queue.tiles.clear();
+    }
     if mouse_input.just_pressed(MouseButton::Left) {
-       // println!("Clicked Something {}", tile_query.iter().len());
-        let mouse_tile = map::get_tile(mouse.0.x, mouse.0.y);
-        click_event.send(ClickEvent((mouse_tile.0, mouse_tile.1), tool.clone()));
+        drag.start = Some(mouse_tile);
+    }
+    if drag.start.is_none() {
+        return;
+    }
+    if drag.end != mouse_tile {
+        drag.end = mouse_tile;
+    }
+    if mouse_input.just_released(MouseButton::Left) {
+        // Only what's in reach now, nearest first
+        let player = map::get_tile(transform.translation.x, transform.translation.y);
+        let mut tiles: Vec<(i32, i32)> = drag.tiles().filter(|tile| in_reach(transform.translation, stats, *tile)).collect();
+        tiles.sort_by_key(|(x, y)| ((x - player.0).pow(2) + (y - player.1).pow(2), *x, *y));
+        *queue = WorkQueue { tool: Some(*tool), tiles: tiles.into() };
+        drag.start = None;
     }
-    
 }
 
-pub fn react_to_mouse_event(
-    mut ev_levelup: EventReader<ClickEvent>,
-    tile_query: Query<(Entity, &mut map::TileState, &map::Position, &mut Sprite)>,
-    assets: Res<AssetServer>,
+/// Highlight every tile under the drag, red where it's out of reach
+pub fn preview_selection(
     mut commands: Commands,
+    drag: Res<DragSelect>,
+    game_assets: Res<GameAssets>,
+    player_query: Query<(&Transform, &PlayerStats), With<Player>>,
+    previews: Query<Entity, With<DragPreview>>,
+) {
+    if !drag.is_changed() {
+        return;
+    }
+    for entity in previews.iter() {
+        commands.entity(entity).despawn();
+    }
+    if drag.start.is_none() {
+        return;
+    }
+    let Ok((player, stats)) = player_query.get_single() else { return; };
+    for tile in drag.tiles() {
+        let (x, y) = map::get_world(tile.0, tile.1);
+        let color = if in_reach(player.translation, stats, tile) { DRAG_TINT } else { OUT_OF_REACH };
+        commands.spawn((
+            SpriteBundle {
+                texture: game_assets.highlight.clone(),
+                sprite: Sprite { color, ..default() },
+                transform: Transform::from_xyz(x, y, 1.),
+                ..default()
+            },
+            DragPreview,
+        ));
+    }
+}
+
+/// Whether using a tool on a tile would do anything right now
+#[allow(clippy::too_many_arguments)]
+fn tool_applies(
+    tool: PlayerTool,
+    tile: (i32, i32),
+    tile_map: &map::TileMap,
+    map_size: &MapSize,
+    buildings: &Buildings,
+    placed: &Query<&Building>,
+    bases: &Bases,
+    cycle: &DayNightCycle,
+    stockpile: &Stockpile,
+    tools: &Tools,
+) -> bool {
+    let Some(target) = tile_map.get(tile.0, tile.1) else { return false; };
+    let state = target.state;
+    let occupied = buildings.occupied(tile.0, tile.1);
+    match tool {
+        PlayerTool::Tiller => !tools.broken() && (
+            tools.hoe_tiles(tile).any(|(x, y)| tile_map.get(x, y).is_some_and(|tile| tile.state == map::TileState::Untoiled))
+            || (bases::mending_hours(cycle) && bases.at(tile).is_some_and(|i| bases.0[i].damaged()))
+        ),
+        PlayerTool::Planter => state == map::TileState::Toiled && !occupied,
+        PlayerTool::Rake => occupied || matches!(state, map::TileState::Toiled | map::TileState::Planted),
+        PlayerTool::Build(kind) => stockpile.0 >= kind.cost()
+            && building::can_place(kind, tile, tile_map, map_size, buildings, |tile| walls::is_wall(buildings, placed, tile)),
+    }
+}
+
+/// Work the next queued tile once the last swing is done. Tiles that have nothing to
+/// do or went out of reach are skipped, and building stops once resources run out.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn work_queue(
+    mut player_query: Query<(&Transform, &PlayerStats, &mut ToolUse, &mut spriteanims::Facing, &mut spriteanims::SpriteAnimator), With<Player>>,
+    mut queue: ResMut<WorkQueue>,
+    tile_map: Res<map::TileMap>,
+    map_size: Res<MapSize>,
+    buildings: Res<Buildings>,
+    placed: Query<&Building>,
+    bases: Res<Bases>,
+    cycle: Res<DayNightCycle>,
+    stockpile: Res<Stockpile>,
+    tools: Res<Tools>,
+    mut click_event: EventWriter<ClickEvent>,
 ){
-    {
-        for ev in ev_levelup.read() {
-            for (e, s, p, _) in &tile_query{
-                let tile_tile = map::get_tile(p.0.x, p.0.y);
-                
-                if ev.0 == tile_tile {
-                   // println!("Clicked Tile: ({},{}) has tile state: {}", mouse_tile.0, mouse_tile.1, s.value());
-                    match ev.1 {
-                        PlayerTool::Planter => {
-                            match *s {
-                                map::TileState::Toiled => {
-                                    commands.entity(e).despawn();
-                                    let rng = rand::thread_rng().gen_range(0..3);
-                                    let str = match rng {
-                                        0 => String::from("tiles/farmtile_seeds_green.png"),
-                                        1 => String::from("tiles/farmtile_seeds_pink.png"),
-                                        _ => String::from("tiles/farmtile_seeds_yellow.png"),
-                                    };
-                                    commands.spawn(
-                                        map::TileBundle::new(assets.load(str), tile_tile.0, tile_tile.1, map::TileState::Planted)
-                                    );
-                                },
-                                _ => {}
-                            }
-                        },
-                        PlayerTool::Tiller => {
-                            match *s {
-                                map::TileState::Untoiled => {
-                                    commands.entity(e).despawn();
-                                    
-                                    commands.spawn(
-                                        map::TileBundle::new(assets.load("tiles/farmtile.png"), tile_tile.0, tile_tile.1, map::TileState::Toiled)
-                                    );
-                                },
-                                _ => {}
-                            }
-                        },
-                        PlayerTool::Rake => {
-                            match *s {
-                                map::TileState::Immutable => {
-                                    
-                                },
-                                _ => {
-                                    commands.entity(e).despawn();
-                                    
-                                    commands.spawn(
-                                        map::TileBundle::new(assets.load("tiles/redgrass.png"), tile_tile.0, tile_tile.1, map::TileState::Untoiled)
-                                    );
-                                }
-                            }
-                        },
-                    }
-                    
-                }
-            }
+    let Ok((transform, stats, mut tool_use, mut facing, mut animator)) = player_query.get_single_mut() else { return; };
+    let Some(tool) = queue.tool else { return; };
+    if !tool_use.0.finished() {
+        return;
+    }
+    if let PlayerTool::Build(kind) = tool {
+        if stockpile.0 < kind.cost() {
+            queue.tiles.clear();
+            return;
         }
-        
     }
+    let next = std::iter::from_fn(|| queue.tiles.pop_front())
+        .find(|tile| in_reach(transform.translation, stats, *tile) && tool_applies(tool, *tile, &tile_map, &map_size, &buildings, &placed, &bases, &cycle, &stockpile, &tools));
+    let Some(tile) = next else { return; };
+    click_event.send(ClickEvent(tile, tool));
+
+    let (tile_x, tile_y) = map::get_world(tile.0, tile.1);
+    let to_tile = Vec2::new(tile_x, tile_y) - transform.translation.truncate();
+    *facing = match (to_tile.x.abs() > to_tile.y.abs(), to_tile.x > 0., to_tile.y > 0.) {
+        (true, true, _) => spriteanims::Facing::Right,
+        (true, false, _) => spriteanims::Facing::Left,
+        (false, _, true) => spriteanims::Facing::Up,
+        (false, _, false) => spriteanims::Facing::Down,
+    };
+    // Better hoes get through the tilling quicker
+    let use_time = if tool == PlayerTool::Tiller { stats.tool_use_time * tools.hoe.cooldown() } else { stats.tool_use_time };
+    tool_use.0 = Timer::from_seconds(use_time, TimerMode::Once);
+    animator.restart(spriteanims::clip_name(spriteanims::Action::Attack, *facing));
 }
 
-/*
-for (e, s, p, _) in &tile_query{
-            let tile_tile = map::get_tile(p.0.x, p.0.y);
-            
-            if mouse_tile == tile_tile {
-               // println!("Clicked Tile: ({},{}) has tile state: {}", mouse_tile.0, mouse_tile.1, s.value());
-                match tool {
-                    PlayerTool::Planter => {
-                        match *s {
-                            map::TileState::Toiled => {
-                                commands.entity(e).despawn();
-                                let rng = rand::thread_rng().gen_range(0..3);
-                                let str = match rng {
-                                    0 => String::from("tiles/farmtile_seeds_green.png"),
-                                    1 => String::from("tiles/farmtile_seeds_pink.png"),
-                                    _ => String::from("tiles/farmtile_seeds_yellow.png"),
-                                };
-                                commands.spawn(
-                                    map::TileBundle::new(assets.load(str), tile_tile.0, tile_tile.1, map::TileState::Planted)
-                                );
-                            },
-                            _ => {}
-                        }
-                    },
-                    PlayerTool::Tiller => {
-                        match *s {
-                            map::TileState::Untoiled => {
-                                commands.entity(e).despawn();
-                                
-                                commands.spawn(
-                                    map::TileBundle::new(assets.load("tiles/farmtile.png"), tile_tile.0, tile_tile.1, map::TileState::Toiled)
-                                );
-                            },
-                            _ => {}
-                        }
+#[allow(clippy::too_many_arguments)]
+pub fn react_to_mouse_event(
+    mut ev_levelup: EventReader<ClickEvent>,
+    mut tile_map: ResMut<map::TileMap>,
+    mut crops: ResMut<Crops>,
+    mut stockpile: ResMut<Stockpile>,
+    buildings: Res<Buildings>,
+    bases: Res<Bases>,
+    mut ev_effects: EventWriter<SpawnEffectEvent>,
+    mut ev_sfx: EventWriter<SfxEvent>,
+    mut rng: ResMut<RunRng>,
+    mut tools: ResMut<Tools>,
+){
+    for ev in ev_levelup.read() {
+        let (x, y) = ev.0;
+        let Some(tile) = tile_map.get(x, y).copied() else { continue; };
+        let (world_x, world_y) = map::get_world(x, y);
+        let position = Vec2::new(world_x, world_y);
+
+        match ev.1 {
+            PlayerTool::Planter => {
+                if buildings.occupied(x, y) {
+                    continue;
+                }
+                if let map::TileState::Toiled = tile.state {
+                    let kind = match rng.0.gen_range(0..3) {
+                        0 => map::TileKind::SeedsGreen,
+                        1 => map::TileKind::SeedsPink,
+                        _ => map::TileKind::SeedsYellow,
+                    };
+                    tile_map.set(x, y, map::Tile::new(kind, map::TileState::Planted));
+                    ev_effects.send(SpawnEffectEvent { effect: EffectKind::PlantingSparkle, position });
+                    ev_sfx.send(SfxEvent(Sfx::Planting));
+                }
+            },
+            PlayerTool::Tiller => {
+                // A broken hoe won't break ground, and better hoes work more of it at once
+                if tools.broken() {
+                    continue;
+                }
+                let mut tilled = false;
+                for (x, y) in tools.hoe_tiles((x, y)) {
+                    if tile_map.get(x, y).is_some_and(|tile| tile.state == map::TileState::Untoiled) {
+                        tile_map.set(x, y, map::Tile::new(map::TileKind::FarmTile, map::TileState::Toiled));
+                        let (world_x, world_y) = map::get_world(x, y);
+                        ev_effects.send(SpawnEffectEvent { effect: EffectKind::TillingDust, position: Vec2::new(world_x, world_y) });
+                        tilled = true;
+                    }
+                }
+                if tilled {
+                    tools.wear_hoe();
+                    ev_sfx.send(SfxEvent(Sfx::ToolUse));
+                }
+            },
+            PlayerTool::Rake => {
+                match tile.state {
+                    map::TileState::Immutable => {
+                        
                     },
-                    PlayerTool::Rake => {
-                        match *s {
-                            map::TileState::Immutable => {
-                                
-                            },
-                            _ => {
-                                commands.entity(e).despawn();
-                                
-                                commands.spawn(
-                                    map::TileBundle::new(assets.load("tiles/redgrass.png"), tile_tile.0, tile_tile.1, map::TileState::Untoiled)
-                                );
-                            }
+                    _ => {
+                        // Ripe crops pay out when they're cleared
+                        if crops.is_ripe(x, y) {
+                            stockpile.0 += HARVEST_YIELD + bases.harvest_bonus();
+                            crops.0.remove(&(x, y));
+                            ev_effects.send(SpawnEffectEvent { effect: EffectKind::HarvestBurst, position });
                         }
-                    },
+                        tile_map.set(x, y, map::Tile::new(map::TileKind::RedGrass, map::TileState::Untoiled));
+                        ev_sfx.send(SfxEvent(Sfx::ToolUse));
+                    }
                 }
-                
-            }
+            },
+            // Placed by building::place_buildings
+            PlayerTool::Build(_) => {},
         }
-*/
\ No newline at end of file
+    }
+}
@@ -0,0 +1,454 @@
+//! Records what the player does each fixed tick so a run can be played back exactly.
+//! `SolFarmer replay [file]` opens one in a viewer with pause, step and speed controls.
+
+use std::{
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+use bevy::{asset::io::file::FileAssetReader, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bases::Bases,
+    building::{Building, Buildings, Health},
+    crops::Crops,
+    day_night::{DayCount, DayPhase, PhaseChangedEvent, TimeControlEvent},
+    game::{Stockpile, Tick},
+    loading::GameAssets,
+    map::TileMap,
+    player::{ClickEvent, Player, PlayerTool, SwingEvent},
+    run::{CharacterProfile, Run},
+    the_core::CoreHealth,
+    tools::{ToolShopEvent, Tools},
+    undo::UndoEvent,
+    weather::Weather,
+};
+
+/// Where runs get recorded to, next to the save file
+const REPLAY_FILE: &str = "solfarmer_replay.ron";
+/// Playback speeds the viewer steps through
+const REPLAY_SPEEDS: [f32; 5] = [0.5, 1., 2., 4., 8.];
+
+/// Something the player did that changes how the run plays out.
+/// Game speed isn't here, it only changes how fast ticks come, not what's in them.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum ReplayInput {
+    Click(i32, i32, PlayerTool),
+    ReadyForNight,
+    Shop(ToolShopEvent),
+    Undo,
+    /// Where the player walked to, exactly, since reach and walls care about fractions of a pixel
+    Move(f32, f32),
+    /// A swing of the hoe from where the player stood
+    Swing(f32, f32),
+    /// A line run in the dev console
+    Console(String),
+}
+
+/// A dev console command to run this tick. Recorded like any other input, though only
+/// builds with the console act on it.
+#[derive(Event, Clone, PartialEq, Eq, Debug)]
+pub struct ConsoleLine(pub String);
+
+/// A recorded run: the seed, every input by the tick it happened on, and a checksum
+/// of the world at the start of each day
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    pub profile: CharacterProfile,
+    pub tool_wear: bool,
+    /// Ticks the recording runs for
+    pub ticks: u32,
+    pub actions: Vec<(u32, ReplayInput)>,
+    /// Day and the world's checksum as it dawned
+    pub checksums: Vec<(u32, u64)>,
+}
+
+impl Replay {
+    pub fn new(run: &Run) -> Replay {
+        Replay { seed: run.seed, profile: run.profile, tool_wear: run.tool_wear, ticks: 0, actions: Vec::new(), checksums: Vec::new() }
+    }
+
+    /// Settings to play the recorded run again with
+    pub fn run(&self) -> Run {
+        Run { seed: self.seed, profile: self.profile, tool_wear: self.tool_wear }
+    }
+
+    /// Where the game records to, and where the viewer looks if it isn't given a file
+    pub fn default_path() -> PathBuf {
+        FileAssetReader::get_base_path().join(REPLAY_FILE)
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let text = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        ron::from_str(&text).map_err(|err| err.to_string())
+    }
+
+    /// Kept on one line, long runs have a lot of clicks
+    pub fn write(&self, path: &Path) {
+        let result = ron::to_string(self)
+            .map_err(|err| err.to_string())
+            .and_then(|text| std::fs::write(path, text).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            warn!("Could not write replay: {}", err);
+        }
+    }
+}
+
+/// Records the current run
+#[derive(Resource)]
+pub struct ReplayRecorder(pub Replay);
+
+/// Plays a recorded run back in place of the player
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    pub replay: Replay,
+    /// Next action to send
+    cursor: usize,
+    /// Days whose checksum has been compared
+    pub checked: usize,
+    /// First day the world didn't match the recording
+    pub diverged: Option<u32>,
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: Replay) -> ReplayPlayback {
+        ReplayPlayback { replay, cursor: 0, checked: 0, diverged: None }
+    }
+}
+
+/// FNV-1a, spelled out so checksums stay the same across Rust versions and platforms
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// Start recording a fresh run, unless we're watching one
+pub fn start_recording(mut commands: Commands, run: Res<Run>, playback: Option<Res<ReplayPlayback>>) {
+    if playback.is_none() {
+        commands.insert_resource(ReplayRecorder(Replay::new(&run)));
+    }
+}
+
+/// Note down this tick's inputs, seen before the rules act on them
+#[allow(clippy::too_many_arguments)]
+pub fn record_inputs(
+    tick: Res<Tick>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut ev_click: EventReader<ClickEvent>,
+    mut ev_controls: EventReader<TimeControlEvent>,
+    mut ev_shop: EventReader<ToolShopEvent>,
+    mut ev_undo: EventReader<UndoEvent>,
+    mut ev_console: EventReader<ConsoleLine>,
+    mut ev_swing: EventReader<SwingEvent>,
+    players: Query<&Transform, With<Player>>,
+    mut last_position: Local<Option<Vec2>>,
+) {
+    let replay = &mut recorder.0;
+    for ClickEvent((x, y), tool) in ev_click.read() {
+        replay.actions.push((tick.0, ReplayInput::Click(*x, *y, *tool)));
+    }
+    for ev in ev_controls.read() {
+        if *ev == TimeControlEvent::ReadyForNight {
+            replay.actions.push((tick.0, ReplayInput::ReadyForNight));
+        }
+    }
+    for ev in ev_shop.read() {
+        replay.actions.push((tick.0, ReplayInput::Shop(*ev)));
+    }
+    for _ in ev_undo.read() {
+        replay.actions.push((tick.0, ReplayInput::Undo));
+    }
+    for ConsoleLine(line) in ev_console.read() {
+        replay.actions.push((tick.0, ReplayInput::Console(line.clone())));
+    }
+    for SwingEvent(position) in ev_swing.read() {
+        replay.actions.push((tick.0, ReplayInput::Swing(position.x, position.y)));
+    }
+    if let Ok(transform) = players.get_single() {
+        let position = transform.translation.truncate();
+        if *last_position != Some(position) {
+            replay.actions.push((tick.0, ReplayInput::Move(position.x, position.y)));
+            *last_position = Some(position);
+        }
+    }
+    replay.ticks = tick.0 + 1;
+}
+
+/// Send the inputs recorded for this tick
+#[allow(clippy::too_many_arguments)]
+pub fn play_inputs(
+    tick: Res<Tick>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut ev_click: EventWriter<ClickEvent>,
+    mut ev_controls: EventWriter<TimeControlEvent>,
+    mut ev_shop: EventWriter<ToolShopEvent>,
+    mut ev_undo: EventWriter<UndoEvent>,
+    mut ev_console: EventWriter<ConsoleLine>,
+    mut ev_swing: EventWriter<SwingEvent>,
+    mut players: Query<&mut Transform, With<Player>>,
+) {
+    let playback = &mut *playback;
+    while let Some((at, input)) = playback.replay.actions.get(playback.cursor) {
+        if *at > tick.0 {
+            break;
+        }
+        match input {
+            ReplayInput::Click(x, y, tool) => { ev_click.send(ClickEvent((*x, *y), *tool)); }
+            ReplayInput::ReadyForNight => { ev_controls.send(TimeControlEvent::ReadyForNight); }
+            ReplayInput::Shop(ev) => { ev_shop.send(*ev); }
+            ReplayInput::Undo => { ev_undo.send(UndoEvent); }
+            ReplayInput::Move(x, y) => {
+                for mut transform in players.iter_mut() {
+                    transform.translation.x = *x;
+                    transform.translation.y = *y;
+                }
+            }
+            ReplayInput::Console(line) => { ev_console.send(ConsoleLine(line.clone())); }
+            ReplayInput::Swing(x, y) => { ev_swing.send(SwingEvent(Vec2::new(*x, *y))); }
+        }
+        playback.cursor += 1;
+    }
+}
+
+/// Checksum the world each dawn. Recording keeps it, playback checks it still matches.
+#[allow(clippy::too_many_arguments)]
+pub fn check_day(
+    mut ev_phase: EventReader<PhaseChangedEvent>,
+    tile_map: Res<TileMap>,
+    crops: Res<Crops>,
+    buildings: Res<Buildings>,
+    placed: Query<(&Building, &Health)>,
+    stockpile: Res<Stockpile>,
+    day: Res<DayCount>,
+    weather: Res<Weather>,
+    core: Res<CoreHealth>,
+    tools: Res<Tools>,
+    bases: Res<Bases>,
+    recorder: Option<ResMut<ReplayRecorder>>,
+    playback: Option<ResMut<ReplayPlayback>>,
+) {
+    if !ev_phase.read().any(|ev| ev.to == DayPhase::Dawn) {
+        return;
+    }
+
+    let mut hasher = Fnv::default();
+    tile_map.tiles().hash(&mut hasher);
+    // Maps iterate in any old order, so go through them by tile
+    let mut planted: Vec<_> = crops.0.iter().collect();
+    planted.sort_by_key(|(tile, _)| **tile);
+    for (tile, crop) in planted {
+        (tile, crop.growth.to_bits(), crop.chill.to_bits()).hash(&mut hasher);
+    }
+    let mut standing: Vec<_> = buildings.0.iter().filter_map(|(tile, entity)| Some((*tile, placed.get(*entity).ok()?))).collect();
+    standing.sort_by_key(|(tile, _)| *tile);
+    for (tile, (building, health)) in standing {
+        (tile, building.0, health.0.to_bits()).hash(&mut hasher);
+    }
+    (stockpile.0, day.0, weather.kind, core.0.to_bits()).hash(&mut hasher);
+    (tools.hoe.index(), tools.durability).hash(&mut hasher);
+    for base in bases.0.iter() {
+        base.health.to_bits().hash(&mut hasher);
+    }
+    let checksum = (day.0, hasher.finish());
+
+    if let Some(mut recorder) = recorder {
+        recorder.0.checksums.push(checksum);
+    }
+    if let Some(mut playback) = playback {
+        let expected = playback.replay.checksums.get(playback.checked).copied();
+        if expected.is_none() {
+            return;
+        }
+        playback.checked += 1;
+        if expected != Some(checksum) && playback.diverged.is_none() {
+            warn!("Replay went out of sync on day {}", day.0);
+            playback.diverged = Some(day.0);
+        }
+    }
+}
+
+/// Keep the file up to date in case the game doesn't get to close cleanly
+pub fn save_replay_each_day(mut ev_phase: EventReader<PhaseChangedEvent>, recorder: Res<ReplayRecorder>) {
+    if ev_phase.read().any(|ev| ev.to == DayPhase::Dawn) {
+        recorder.0.write(&Replay::default_path());
+    }
+}
+
+pub fn save_replay(recorder: Res<ReplayRecorder>) {
+    recorder.0.write(&Replay::default_path());
+}
+
+/// A finished run stops recording, and a watched one goes back to being played
+pub fn stop_replay(mut commands: Commands) {
+    commands.remove_resource::<ReplayRecorder>();
+    commands.remove_resource::<ReplayPlayback>();
+}
+
+/// Replay status, under the weather banner
+#[derive(Component)]
+pub struct ReplayLabel;
+
+pub fn setup_viewer(mut commands: Commands, game_assets: Res<GameAssets>) {
+    commands.spawn((ReplayLabel, TextBundle::from_section(
+        "",
+        TextStyle {
+            font: game_assets.ui_font.clone(),
+            font_size: 20.0,
+            color: Color::WHITE,
+        },
+    ).with_style(Style {
+        position_type: PositionType::Absolute,
+        top: Val::Px(48.0),
+        left: Val::Px(10.0),
+        ..default()
+    })));
+}
+
+/// Space pauses, period steps one tick while paused, and the brackets change speed.
+/// Runs before the fixed ticks so a step lands this frame.
+pub fn viewer_controls(
+    keycode: Res<ButtonInput<KeyCode>>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    if keycode.just_pressed(KeyCode::Space) {
+        if virtual_time.is_paused() {
+            virtual_time.unpause();
+        } else {
+            virtual_time.pause();
+        }
+    }
+    if keycode.just_pressed(KeyCode::Period) && virtual_time.is_paused() {
+        // Just enough time for the next tick to run
+        let step = fixed_time.timestep().saturating_sub(fixed_time.overstep());
+        virtual_time.advance_by(step);
+    }
+
+    let speed = REPLAY_SPEEDS.iter().position(|speed| *speed == virtual_time.relative_speed()).unwrap_or(1);
+    let speed = if keycode.just_pressed(KeyCode::BracketLeft) {
+        speed.saturating_sub(1)
+    } else if keycode.just_pressed(KeyCode::BracketRight) {
+        (speed + 1).min(REPLAY_SPEEDS.len() - 1)
+    } else {
+        return;
+    };
+    virtual_time.set_relative_speed(REPLAY_SPEEDS[speed]);
+}
+
+/// Show where the replay is up to, and stop at the end of it
+pub fn update_viewer(
+    tick: Res<Tick>,
+    playback: Res<ReplayPlayback>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    mut label: Query<&mut Text, With<ReplayLabel>>,
+) {
+    if tick.0 >= playback.replay.ticks && !virtual_time.is_paused() {
+        virtual_time.pause();
+    }
+
+    let state = if tick.0 >= playback.replay.ticks {
+        "end".to_string()
+    } else if virtual_time.is_paused() {
+        "paused".to_string()
+    } else {
+        format!("{}x", virtual_time.relative_speed())
+    };
+    let (sync, color) = match playback.diverged {
+        Some(day) => (format!("out of sync since day {}", day), Color::RED),
+        None => (format!("{} days checked", playback.checked), Color::GREEN),
+    };
+    for mut text in label.iter_mut() {
+        text.sections[0].value = format!("Replay  tick {}/{}  {}  {}", tick.0, playback.replay.ticks, state, sync);
+        text.sections[0].style.color = color;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{building::Buildable, map::TileState, sim::{self, SimSettings}};
+
+    #[test]
+    fn replay_plays_out_the_same() {
+        let settings = SimSettings { seed: 11, days: 2, profile: CharacterProfile::default() };
+        let mut recorded = sim::bot_app(&settings);
+        let summary = sim::play_days(&mut recorded, &settings);
+        let replay = recorded.world.resource::<ReplayRecorder>().0.clone();
+        assert!(!replay.actions.is_empty());
+        assert_eq!(replay.checksums.len(), 2);
+
+        // Through the file format and back, then with nobody at the controls
+        let replay: Replay = ron::from_str(&ron::to_string(&replay).unwrap()).unwrap();
+        let run = replay.run();
+        let checksums = replay.checksums.len();
+        let mut played = sim::headless_app(run, |app| {
+            app.insert_resource(ReplayPlayback::new(replay));
+        });
+        assert_eq!(sim::play_days(&mut played, &settings), summary);
+        let playback = played.world.resource::<ReplayPlayback>();
+        assert_eq!(playback.checked, checksums);
+        assert_eq!(playback.diverged, None);
+    }
+
+    #[test]
+    fn replay_puts_the_player_back_exactly() {
+        let run = Run { seed: 3, profile: CharacterProfile::default(), tool_wear: true };
+        let start = Transform::from_xyz(640.25, 300.5, 0.);
+        let mut recorded = sim::headless_app(run.clone(), |_| {});
+        recorded.update();
+        let player = recorded.world.spawn((Player, start)).id();
+        let tile_map = recorded.world.resource::<TileMap>();
+        let open: Vec<(i32, i32)> = (0..tile_map.width())
+            .flat_map(|x| (0..tile_map.height()).map(move |y| (x, y)))
+            .filter(|(x, y)| tile_map.get(*x, *y).is_some_and(|tile| tile.state == TileState::Untoiled))
+            .take(2)
+            .collect();
+
+        // Walk in steps that never land on a whole pixel, working the ground on the way
+        for step in 0..40 {
+            recorded.world.get_mut::<Transform>(player).unwrap().translation += Vec3::new(1.37, -0.61, 0.);
+            if step == 10 {
+                recorded.world.send_event(ClickEvent(open[0], PlayerTool::Tiller));
+            }
+            if step == 20 {
+                recorded.world.send_event(ClickEvent(open[1], PlayerTool::Build(Buildable::Torch)));
+            }
+            recorded.update();
+        }
+        let replay = recorded.world.resource::<ReplayRecorder>().0.clone();
+        assert!(replay.actions.iter().any(|(_, input)| matches!(input, ReplayInput::Move(..))));
+
+        let replay: Replay = ron::from_str(&ron::to_string(&replay).unwrap()).unwrap();
+        let mut played = sim::headless_app(run, |app| {
+            app.insert_resource(ReplayPlayback::new(replay));
+        });
+        played.update();
+        let ghost = played.world.spawn((Player, start)).id();
+        for _ in 0..40 {
+            played.update();
+        }
+
+        assert_eq!(played.world.get::<Transform>(ghost), recorded.world.get::<Transform>(player));
+        assert_eq!(played.world.resource::<TileMap>().tiles(), recorded.world.resource::<TileMap>().tiles());
+        assert_eq!(played.world.resource::<Stockpile>().0, recorded.world.resource::<Stockpile>().0);
+        assert!(played.world.resource::<Buildings>().occupied(open[1].0, open[1].1));
+    }
+}
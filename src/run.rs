@@ -0,0 +1,92 @@
+use bevy::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+/// Which character the player picked for a run. Mostly cosmetic, each has a small perk
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum CharacterProfile {
+    #[default]
+    Ed,
+    Drifter,
+    ZombieSlayer,
+}
+
+/// Gameplay tweaks a profile brings along, as multipliers on the defaults
+pub struct ProfileTraits {
+    pub move_speed: f32,
+    /// Scales the hoe swing cooldown and how long each tool use takes, lower works faster
+    pub swing_cooldown: f32,
+}
+
+impl CharacterProfile {
+    pub const ALL: [CharacterProfile; 3] = [
+        CharacterProfile::Ed,
+        CharacterProfile::Drifter,
+        CharacterProfile::ZombieSlayer,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CharacterProfile::Ed => "Ed",
+            CharacterProfile::Drifter => "Drifter",
+            CharacterProfile::ZombieSlayer => "Zombie Slayer",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            CharacterProfile::Ed => "Just a farmer",
+            CharacterProfile::Drifter => "Moves faster",
+            CharacterProfile::ZombieSlayer => "Works faster",
+        }
+    }
+
+    /// Key into `GameAssets::character_sheets`
+    pub fn sheet(&self) -> &'static str {
+        match self {
+            CharacterProfile::Ed => "ed_sheet",
+            CharacterProfile::Drifter => "human_base",
+            CharacterProfile::ZombieSlayer => "zombie_slayer",
+        }
+    }
+
+    pub fn traits(&self) -> ProfileTraits {
+        match self {
+            CharacterProfile::Ed => ProfileTraits { move_speed: 1., swing_cooldown: 1. },
+            CharacterProfile::Drifter => ProfileTraits { move_speed: 1.2, swing_cooldown: 1. },
+            CharacterProfile::ZombieSlayer => ProfileTraits { move_speed: 1., swing_cooldown: 0.75 },
+        }
+    }
+}
+
+/// Settings for the current run, picked before it starts
+#[derive(Resource, Clone)]
+pub struct Run {
+    /// Drives everything random about the run that should repeat for the same seed, like the weather
+    pub seed: u64,
+    pub profile: CharacterProfile,
+    /// Whether tools wear out with use and need mending
+    pub tool_wear: bool,
+}
+
+impl Run {
+    pub fn new(profile: CharacterProfile) -> Run {
+        Run { seed: rand::random(), profile, tool_wear: true }
+    }
+}
+
+impl Default for Run {
+    fn default() -> Self {
+        Run::new(CharacterProfile::default())
+    }
+}
+
+/// Randomness for the run's gameplay, seeded from `Run::seed` so the same seed plays out the same way
+#[derive(Resource)]
+pub struct RunRng(pub StdRng);
+
+impl RunRng {
+    pub fn new(seed: u64) -> RunRng {
+        RunRng(StdRng::seed_from_u64(seed))
+    }
+}
@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+
+use bevy::{asset::io::file::FileAssetReader, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::run::CharacterProfile;
+
+const SAVE_FILE: &str = "solfarmer_save.ron";
+
+/// Things we remember between sessions
+#[derive(Resource, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SaveData {
+    /// Profile picked for the last run, preselected next time
+    pub last_profile: CharacterProfile,
+    /// Tools wear out and need mending, turn off for a gentler game
+    pub tool_wear: bool,
+}
+
+impl Default for SaveData {
+    fn default() -> Self {
+        SaveData { last_profile: CharacterProfile::default(), tool_wear: true }
+    }
+}
+
+impl SaveData {
+    fn path() -> PathBuf {
+        FileAssetReader::get_base_path().join(SAVE_FILE)
+    }
+
+    /// Read the save file, falling back to defaults if it's missing or unreadable
+    pub fn load() -> SaveData {
+        let Ok(text) = std::fs::read_to_string(Self::path()) else { return SaveData::default(); };
+        ron::from_str(&text).unwrap_or_else(|err| {
+            warn!("Ignoring unreadable save file: {}", err);
+            SaveData::default()
+        })
+    }
+
+    pub fn write(&self) {
+        let result = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| err.to_string())
+            .and_then(|text| std::fs::write(Self::path(), text).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            warn!("Could not write save file: {}", err);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use bevy::prelude::*;
+#[cfg(feature = "audio")]
+use bevy::utils::HashMap;
+#[cfg(feature = "audio")]
+use bevy_kira_audio::prelude::*;
+#[cfg(feature = "audio")]
+use rand::{seq::SliceRandom, Rng};
+
+use crate::day_night::{DayPhase, PhaseChangedEvent};
+#[cfg(feature = "audio")]
+use crate::loading::GameAssets;
+
+/// Audio channel all sound effects play on, separate from the music
+#[derive(Resource)]
+pub struct SfxChannel;
+
+/// Sound effects the game can ask for, each one a set of variations
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Sfx {
+    ButtonPress,
+    ToolUse,
+    Planting,
+    CoreDamage,
+    WaveStart,
+}
+
+impl Sfx {
+    pub const ALL: [Sfx; 5] = [Sfx::ButtonPress, Sfx::ToolUse, Sfx::Planting, Sfx::CoreDamage, Sfx::WaveStart];
+
+    /// Variations to pick from, one is chosen at random each time. Loaded with the rest of `GameAssets`.
+    pub fn paths(&self) -> &'static [&'static str] {
+        match self {
+            Sfx::ButtonPress => &["sfx/button_1.wav", "sfx/button_2.wav"],
+            Sfx::ToolUse => &["sfx/tool_1.wav", "sfx/tool_2.wav", "sfx/tool_3.wav"],
+            Sfx::Planting => &["sfx/plant_1.wav", "sfx/plant_2.wav"],
+            Sfx::CoreDamage => &["sfx/core_damage.wav"],
+            Sfx::WaveStart => &["sfx/wave_start.wav"],
+        }
+    }
+
+    /// Seconds before the same sound can play again, stops spam turning into noise
+    #[cfg(feature = "audio")]
+    fn cooldown(&self) -> f32 {
+        match self {
+            Sfx::ButtonPress => 0.05,
+            Sfx::ToolUse | Sfx::Planting => 0.08,
+            Sfx::CoreDamage => 0.3,
+            Sfx::WaveStart => 2.,
+        }
+    }
+
+    #[cfg(feature = "audio")]
+    fn volume(&self) -> f64 {
+        match self {
+            Sfx::ButtonPress => 0.5,
+            Sfx::WaveStart | Sfx::CoreDamage => 1.,
+            _ => 0.7,
+        }
+    }
+}
+
+/// Ask for a sound effect to be played
+#[derive(Event, Clone, Copy, Debug)]
+pub struct SfxEvent(pub Sfx);
+
+/// Player's sound effect settings
+#[derive(Resource)]
+pub struct SfxSettings {
+    pub volume: f64,
+    pub muted: bool,
+}
+
+impl Default for SfxSettings {
+    fn default() -> Self {
+        SfxSettings { volume: 0.8, muted: false }
+    }
+}
+
+/// When each sound last played, in real seconds
+#[cfg(feature = "audio")]
+#[derive(Resource, Default)]
+pub struct SfxCooldowns(HashMap<Sfx, f32>);
+
+/// Play requested sounds, each a random variation at a slightly random pitch
+#[cfg(feature = "audio")]
+pub fn play_sfx(
+    mut ev_sfx: EventReader<SfxEvent>,
+    game_assets: Res<GameAssets>,
+    settings: Res<SfxSettings>,
+    mut cooldowns: ResMut<SfxCooldowns>,
+    time: Res<Time<Real>>,
+    channel: Res<AudioChannel<SfxChannel>>,
+) {
+    if settings.muted {
+        ev_sfx.clear();
+        return;
+    }
+    let now = time.elapsed_seconds();
+    let mut rng = rand::thread_rng();
+    for SfxEvent(sfx) in ev_sfx.read() {
+        if cooldowns.0.get(sfx).is_some_and(|last| now - last < sfx.cooldown()) {
+            continue;
+        }
+        let Some(handle) = game_assets.sfx.get(sfx).and_then(|handles| handles.choose(&mut rng)) else { continue; };
+        channel.play(handle.clone())
+            .with_volume(sfx.volume())
+            .with_playback_rate(rng.gen_range(0.92..1.08));
+        cooldowns.0.insert(*sfx, now);
+    }
+}
+
+/// Every UI button clicks when pressed
+pub fn button_sfx(buttons: Query<&Interaction, (Changed<Interaction>, With<Button>)>, mut ev_sfx: EventWriter<SfxEvent>) {
+    for interaction in buttons.iter() {
+        if *interaction == Interaction::Pressed {
+            ev_sfx.send(SfxEvent(Sfx::ButtonPress));
+        }
+    }
+}
+
+/// Keep the channel volume in step with the settings
+#[cfg(feature = "audio")]
+pub fn apply_sfx_settings(settings: Res<SfxSettings>, channel: Res<AudioChannel<SfxChannel>>) {
+    if settings.is_changed() {
+        channel.set_volume(if settings.muted { 0. } else { settings.volume });
+    }
+}
+
+/// Night is when the enemies come
+pub fn phase_sfx(mut ev_phase: EventReader<PhaseChangedEvent>, mut ev_sfx: EventWriter<SfxEvent>) {
+    for ev in ev_phase.read() {
+        if ev.to == DayPhase::Night {
+            ev_sfx.send(SfxEvent(Sfx::WaveStart));
+        }
+    }
+}
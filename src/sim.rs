@@ -0,0 +1,249 @@
+//! Headless runs of the game with a scripted player, for tests and balancing.
+//! `SolFarmer simulate --days 5 --seed 42` prints a JSON summary of the run.
+
+use std::time::Duration;
+
+use bevy::{prelude::*, time::TimeUpdateStrategy};
+use serde::Serialize;
+
+use crate::{
+    building::{Buildable, Buildings},
+    crops::Crops,
+    day_night::{DayCount, DayNightSchedule},
+    game::{self, Stockpile, TickSet},
+    loading::{GameAssets, TileAtlas},
+    map::{self, MapSize, TileMap, TileState},
+    player::{ClickEvent, PlayerTool},
+    run::{CharacterProfile, Run},
+    the_core::CoreHealth,
+    tools::{ToolShopEvent, Tools, REPAIR_COST},
+    weather::{Weather, WeatherKind},
+    AppState,
+};
+
+/// Length of one simulated frame
+const SIM_STEP: f32 = 1. / 30.;
+/// Seconds between the bot's actions, about as fast as a player clicks
+const BOT_ACTION_TIME: f32 = 0.5;
+/// How far out from the middle of the homestead the bot farms
+const FARM_RADIUS: i32 = 6;
+/// Where the bot puts torches, relative to the middle of the farm, just outside the field
+const TORCH_SPOTS: [(i32, i32); 4] = [(-5, -5), (5, -5), (-5, 5), (5, 5)];
+/// Resources the bot keeps back rather than spend on torches
+const TORCH_RESERVE: u32 = 4;
+
+/// What to simulate
+#[derive(Clone, Debug)]
+pub struct SimSettings {
+    pub seed: u64,
+    pub days: u32,
+    pub profile: CharacterProfile,
+}
+
+impl Default for SimSettings {
+    fn default() -> Self {
+        SimSettings { seed: rand::random(), days: 3, profile: CharacterProfile::default() }
+    }
+}
+
+/// How a simulated run went, sampled at the end of each day
+#[derive(Clone, PartialEq, Debug, Serialize)]
+pub struct SimSummary {
+    pub seed: u64,
+    pub days_survived: u32,
+    pub core_health: Vec<f32>,
+    pub resources: Vec<u32>,
+    pub weather: Vec<WeatherKind>,
+    /// Ripe crops the bot brought in over the whole run
+    pub harvests: u32,
+}
+
+impl SimSummary {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("summary is plain data")
+    }
+}
+
+/// Stands in for the player: harvests what's ripe, mends the hoe, replants, lights the field and tills more ground
+#[derive(Resource)]
+struct Bot {
+    timer: Timer,
+    harvests: u32,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn bot_player(
+    time: Res<Time>,
+    mut bot: ResMut<Bot>,
+    tile_map: Res<TileMap>,
+    crops: Res<Crops>,
+    stockpile: Res<Stockpile>,
+    buildings: Res<Buildings>,
+    map_size: Res<MapSize>,
+    tools: Res<Tools>,
+    mut ev_click: EventWriter<ClickEvent>,
+    mut ev_shop: EventWriter<ToolShopEvent>,
+) {
+    if !bot.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let (mid_x, mid_y) = map_size.home(map::HOME_WIDTH / 2, map::HOME_HEIGHT / 2);
+    let field: Vec<(i32, i32)> = (-FARM_RADIUS..=FARM_RADIUS)
+        .flat_map(|dx| (-FARM_RADIUS..=FARM_RADIUS).map(move |dy| (mid_x + dx, mid_y + dy)))
+        .filter(|tile| map::distance_int_from_point(*tile, (mid_x, mid_y)) <= FARM_RADIUS as f32)
+        .collect();
+    let free = |(x, y): (i32, i32), state: TileState| {
+        !buildings.occupied(x, y) && tile_map.get(x, y).is_some_and(|tile| tile.state == state)
+    };
+
+    let ripe = field.iter().find(|(x, y)| crops.is_ripe(*x, *y)).map(|tile| (*tile, PlayerTool::Rake));
+    let plant = || field.iter().find(|tile| free(**tile, TileState::Toiled)).map(|tile| (*tile, PlayerTool::Planter));
+    let light = || {
+        if stockpile.0 < Buildable::Torch.cost() + TORCH_RESERVE {
+            return None;
+        }
+        TORCH_SPOTS.iter()
+            .map(|(dx, dy)| (mid_x + dx, mid_y + dy))
+            .find(|tile| free(*tile, TileState::Untoiled))
+            .map(|tile| (tile, PlayerTool::Build(Buildable::Torch)))
+    };
+    let till = || field.iter().find(|tile| free(**tile, TileState::Untoiled)).map(|tile| (*tile, PlayerTool::Tiller));
+
+    if ripe.is_some() {
+        bot.harvests += 1;
+    }
+    // Mend the hoe when it gives out, the shop only takes it in daylight
+    if ripe.is_none() && tools.broken() && stockpile.0 >= REPAIR_COST {
+        ev_shop.send(ToolShopEvent::RepairHoe);
+        return;
+    }
+    if let Some((tile, tool)) = ripe.or_else(plant).or_else(light).or_else(till) {
+        ev_click.send(ClickEvent(tile, tool));
+    }
+}
+
+/// The game's rules with nothing driving them, without a window, renderer or audio.
+/// `extra` adds whatever plays in place of the player.
+pub(crate) fn headless_app(run: Run, extra: impl FnOnce(&mut App)) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        // Every frame is the same length, so runs don't depend on how fast the machine is
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(SIM_STEP)))
+        .insert_resource(run)
+        .init_resource::<GameAssets>()
+        .init_resource::<TileAtlas>()
+        .insert_state(AppState::Game);
+    extra(&mut app);
+    game::build_simulation(&mut app);
+    app.finish();
+    app.cleanup();
+    app
+}
+
+/// A run that's just got going with nobody playing, for tests to poke at
+#[cfg(test)]
+pub(crate) fn fresh_run() -> App {
+    let mut app = headless_app(Run { seed: 5, profile: CharacterProfile::default(), tool_wear: true }, |_| {});
+    app.update();
+    app
+}
+
+/// The bot playing a fresh run
+pub(crate) fn bot_app(settings: &SimSettings) -> App {
+    headless_app(Run { seed: settings.seed, profile: settings.profile, tool_wear: true }, |app| {
+        app.insert_resource(Bot { timer: Timer::from_seconds(BOT_ACTION_TIME, TimerMode::Repeating), harvests: 0 })
+            .add_systems(FixedUpdate, bot_player.in_set(TickSet::Input).run_if(in_state(AppState::Game)));
+    })
+}
+
+/// Play a run with the bot
+pub fn simulate(settings: &SimSettings) -> SimSummary {
+    let mut app = bot_app(settings);
+    let mut summary = play_days(&mut app, settings);
+    summary.harvests = app.world.resource::<Bot>().harvests;
+    summary
+}
+
+/// Run the app until it's been through the days asked for, or the core falls
+pub(crate) fn play_days(app: &mut App, settings: &SimSettings) -> SimSummary {
+    let mut summary = SimSummary {
+        seed: settings.seed,
+        days_survived: 0,
+        core_health: Vec::new(),
+        resources: Vec::new(),
+        weather: Vec::new(),
+        harvests: 0,
+    };
+    let day_length = app.world.resource::<DayNightSchedule>().day_length();
+    let max_steps = ((settings.days + 1) as f32 * day_length / SIM_STEP) as u64;
+    let mut day = 1;
+    let mut weather = WeatherKind::default();
+
+    for _ in 0..max_steps {
+        app.update();
+        let world = &app.world;
+        let today = world.resource::<DayCount>().0;
+        let health = world.resource::<CoreHealth>().0;
+        if today == day {
+            weather = world.resource::<Weather>().kind;
+            if health > 0. {
+                continue;
+            }
+        }
+
+        // A day ended, or the core did
+        summary.core_health.push(health);
+        summary.resources.push(world.resource::<Stockpile>().0);
+        summary.weather.push(weather);
+        if health <= 0. {
+            break;
+        }
+        summary.days_survived = day;
+        day = today;
+        if day > settings.days {
+            break;
+        }
+    }
+    summary
+}
+
+/// Entry point for `SolFarmer simulate`
+pub fn cli(args: &[String]) {
+    let mut settings = SimSettings::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args.next();
+        let parsed = match arg.as_str() {
+            "--days" => value.and_then(|value| value.parse().ok()).map(|days| settings.days = days),
+            "--seed" => value.and_then(|value| value.parse().ok()).map(|seed| settings.seed = seed),
+            _ => None,
+        };
+        if parsed.is_none() {
+            eprintln!("Usage: SolFarmer simulate [--days N] [--seed S]");
+            std::process::exit(2);
+        }
+    }
+    println!("{}", simulate(&settings).to_json());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_is_json() {
+        let summary = SimSummary {
+            seed: 1,
+            days_survived: 2,
+            core_health: vec![100., 87.5],
+            resources: vec![6, 10],
+            weather: vec![WeatherKind::Clear, WeatherKind::DustStorm],
+            harvests: 4,
+        };
+        assert_eq!(
+            summary.to_json(),
+            r#"{"seed":1,"days_survived":2,"core_health":[100.0,87.5],"resources":[6,10],"weather":["Clear","DustStorm"],"harvests":4}"#
+        );
+    }
+}
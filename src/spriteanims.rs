@@ -1,59 +1,296 @@
-use std::time::Duration;
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext, ReadAssetBytesError},
+    prelude::*,
+    sprite::Anchor,
+    utils::{BoxedFuture, HashMap},
+};
+use serde::Deserialize;
 
-use bevy::prelude::*;
+// Animate the player's character and the enemies
+// Each sprite sheet has a `.anim.ron` file next to the image, pointing at a clip file that
+// sheets with the same layout share. The human sheets only have one cell per facing, so
+// their walk is a bob of that cell rather than separate frames.
 
+pub fn build_plugin(app: &mut App) {
+    app
+        .init_asset::<AnimationSheet>()
+        .init_asset_loader::<AnimationSheetLoader>()
+        .add_systems(Update, (pick_character_clip, animate_sprites).chain());
+}
 
-// Animate a player (or enemy)
-// four states (face left, face right, face down, face up)
+/// Which way a character is looking, also picks the clip direction
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Facing {
+    #[default]
+    Down,
+    Right,
+    Up,
+    Left,
+}
 
-#[derive(Component)]
-pub enum HumanAnimState {
-    FaceDown,
-    FaceRight,
-    FaceUp,
-    FaceLeft
+impl Facing {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Facing::Down => "down",
+            Facing::Right => "right",
+            Facing::Up => "up",
+            Facing::Left => "left",
+        }
+    }
 }
 
+/// What a character is doing, the other half of the clip name
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Idle,
+    Walk,
+    Attack,
+    Hurt,
+    Death,
+}
 
-impl HumanAnimState {
-    pub fn getRect(&self) -> Rect {
+impl Action {
+    pub fn name(&self) -> &'static str {
         match self {
-            HumanAnimState::FaceDown => {Rect{ min: Vec2{ x: 0., y: 0.}, max: Vec2{ x: 31., y: 31.}}},
-            HumanAnimState::FaceRight => {Rect{ min: Vec2{ x: 32., y: 0.}, max: Vec2{ x: 63., y: 31.}}},
-            HumanAnimState::FaceUp => {Rect{ min: Vec2{ x: 64., y: 0.}, max: Vec2{ x: 95., y: 31.}}},
-            HumanAnimState::FaceLeft => {Rect{ min: Vec2{ x: 96., y: 0.}, max: Vec2{ x: 127., y: 31.}}},
-        } 
+            Action::Idle => "idle",
+            Action::Walk => "walk",
+            Action::Attack => "attack",
+            Action::Hurt => "hurt",
+            Action::Death => "death",
+        }
     }
 }
 
+/// Clips are named `<action>_<facing>`, e.g. `walk_left`
+pub fn clip_name(action: Action, facing: Facing) -> String {
+    format!("{}_{}", action.name(), facing.name())
+}
+
+/// Set by movement code so the animator knows to walk
+#[derive(Component, Default)]
+pub struct Moving(pub bool);
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum PlayMode {
+    #[default]
+    Loop,
+    Once,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Frame {
+    /// Cell in the sheet, counted left to right then top to bottom
+    pub index: usize,
+    /// Pixel nudge for the frame, lets single cell sheets bob and shake
+    #[serde(default)]
+    pub offset: (f32, f32),
+    #[serde(default)]
+    pub flip_x: bool,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct SpriteClip {
+    pub frames: Vec<Frame>,
+    /// Seconds each frame stays up
+    pub frame_time: f32,
+    #[serde(default)]
+    pub mode: PlayMode,
+}
+
+/// The `.anim.ron` file layout
+#[derive(Deserialize)]
+struct AnimationSheetFile {
+    image: String,
+    frame_size: (f32, f32),
+    columns: usize,
+    rows: usize,
+    /// Asset path of the clip file, a map of clip names to clips
+    clips: String,
+}
+
+/// Files an `.anim.ron` points at, so they can be checked for before anything loads
+pub fn sheet_dependencies(bytes: &[u8]) -> Result<[String; 2], ron::error::SpannedError> {
+    let file: AnimationSheetFile = ron::de::from_bytes(bytes)?;
+    Ok([file.image, file.clips])
+}
+
+/// A sprite sheet along with the named clips that can be played from it
+#[derive(Asset, TypePath)]
+pub struct AnimationSheet {
+    #[dependency]
+    pub image: Handle<Image>,
+    pub layout: Handle<TextureAtlasLayout>,
+    pub frame_size: Vec2,
+    pub clips: HashMap<String, SpriteClip>,
+}
+
+#[derive(Default)]
+pub struct AnimationSheetLoader;
+
+#[derive(Debug)]
+pub enum AnimationSheetLoaderError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+    Clips(ReadAssetBytesError),
+    /// A clip that would never move on from its first frame
+    FrameTime(String, f32),
+}
+
+impl std::fmt::Display for AnimationSheetLoaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AnimationSheetLoaderError::Io(err) => write!(f, "could not read animation sheet: {}", err),
+            AnimationSheetLoaderError::Ron(err) => write!(f, "could not parse animation sheet: {}", err),
+            AnimationSheetLoaderError::Clips(err) => write!(f, "could not read animation clips: {}", err),
+            AnimationSheetLoaderError::FrameTime(clip, time) => write!(f, "clip {} has a frame_time of {}, it has to be above 0", clip, time),
+        }
+    }
+}
+
+impl std::error::Error for AnimationSheetLoaderError {}
+
+impl AssetLoader for AnimationSheetLoader {
+    type Asset = AnimationSheet;
+    type Settings = ();
+    type Error = AnimationSheetLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<AnimationSheet, AnimationSheetLoaderError>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await.map_err(AnimationSheetLoaderError::Io)?;
+            let file: AnimationSheetFile = ron::de::from_bytes(&bytes).map_err(AnimationSheetLoaderError::Ron)?;
+            let clip_bytes = load_context.read_asset_bytes(file.clips.clone()).await.map_err(AnimationSheetLoaderError::Clips)?;
+            let clips: HashMap<String, SpriteClip> = ron::de::from_bytes(&clip_bytes).map_err(AnimationSheetLoaderError::Ron)?;
+            if let Some((name, clip)) = clips.iter().find(|(_, clip)| clip.frame_time <= 0. || clip.frame_time.is_nan()) {
+                return Err(AnimationSheetLoaderError::FrameTime(name.clone(), clip.frame_time));
+            }
+
+            let frame_size = Vec2::new(file.frame_size.0, file.frame_size.1);
+            let layout = TextureAtlasLayout::from_grid(frame_size, file.columns, file.rows, None, None);
+
+            Ok(AnimationSheet {
+                image: load_context.load(file.image),
+                layout: load_context.add_labeled_asset("layout".to_owned(), layout),
+                frame_size,
+                clips,
+            })
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["anim.ron"]
+    }
+}
+
+/// Plays clips from an animation sheet on a sprite sheet entity
 #[derive(Component)]
-pub struct HumanStateController {
-    state: HumanAnimState
+pub struct SpriteAnimator {
+    pub sheet: Handle<AnimationSheet>,
+    clip: String,
+    frame: usize,
+    elapsed: f32,
+    finished: bool,
 }
 
+impl SpriteAnimator {
+    pub fn new(sheet: Handle<AnimationSheet>, clip: String) -> SpriteAnimator {
+        SpriteAnimator { sheet, clip, frame: 0, elapsed: 0., finished: false }
+    }
+
+    /// Switch clips, restarting only if it's a different clip
+    pub fn play(&mut self, clip: String) {
+        if self.clip != clip {
+            self.restart(clip);
+        }
+    }
 
+    /// Start a clip from its first frame even if it's already playing
+    pub fn restart(&mut self, clip: String) {
+        self.clip = clip;
+        self.frame = 0;
+        self.elapsed = 0.;
+        self.finished = false;
+    }
+}
 
+/// Sprite sheet entity that animates from a sheet, facing down and idle to start
 #[derive(Bundle)]
 pub struct HumanAnimator {
-    sprite_bundle: SpriteBundle,
-    state: HumanAnimState,
+    sprite_bundle: SpriteSheetBundle,
+    animator: SpriteAnimator,
+    facing: Facing,
+    moving: Moving,
 }
 
 impl HumanAnimator {
-    pub fn new(image: Handle<Image>, state: HumanAnimState, t_off: Vec3) -> HumanAnimator {
+    pub fn new(sheet_handle: Handle<AnimationSheet>, sheet: &AnimationSheet, t_off: Vec3) -> HumanAnimator {
         HumanAnimator {
-            sprite_bundle: SpriteBundle {
-                texture: image,
-                sprite: Sprite {
-                    rect: Some(state.getRect()),
-                    ..default()
-                },
+            sprite_bundle: SpriteSheetBundle {
+                texture: sheet.image.clone(),
+                atlas: TextureAtlas { layout: sheet.layout.clone(), index: 0 },
                 transform: Transform::from_xyz(t_off.x, t_off.y, t_off.z),
                 ..default()
             },
-            state: state
+            animator: SpriteAnimator::new(sheet_handle, clip_name(Action::Idle, Facing::Down)),
+            facing: Facing::Down,
+            moving: Moving(false),
+        }
+    }
+}
+
+/// Walk or idle in the facing direction, without cutting off attacks, hurts or a death
+fn pick_character_clip(mut query: Query<(&Facing, &Moving, &mut SpriteAnimator)>) {
+    for (facing, moving, mut animator) in query.iter_mut() {
+        let busy = [Action::Attack, Action::Hurt].iter().any(|a| animator.clip.starts_with(a.name())) && !animator.finished;
+        if busy || animator.clip.starts_with(Action::Death.name()) {
+            continue;
         }
+        let action = if moving.0 { Action::Walk } else { Action::Idle };
+        animator.play(clip_name(action, *facing));
     }
 }
 
+/// Step every animator and push the current frame onto its sprite
+fn animate_sprites(
+    time: Res<Time>,
+    sheets: Res<Assets<AnimationSheet>>,
+    mut query: Query<(&mut SpriteAnimator, &mut TextureAtlas, &mut Sprite)>,
+) {
+    for (mut animator, mut atlas, mut sprite) in query.iter_mut() {
+        let Some(sheet) = sheets.get(&animator.sheet) else { continue; };
+        // Sheets don't have to provide every clip, missing ones just hold the current frame
+        let Some(clip) = sheet.clips.get(&animator.clip) else { continue; };
+        if clip.frames.is_empty() {
+            continue;
+        }
+
+        animator.elapsed += time.delta_seconds();
+        while animator.elapsed >= clip.frame_time && !animator.finished {
+            animator.elapsed -= clip.frame_time;
+            if animator.frame + 1 < clip.frames.len() {
+                animator.frame += 1;
+            } else if clip.mode == PlayMode::Loop {
+                animator.frame = 0;
+            } else {
+                // One shot clips hold their last frame
+                animator.finished = true;
+            }
+        }
 
+        let frame = &clip.frames[animator.frame.min(clip.frames.len() - 1)];
+        if atlas.index != frame.index {
+            atlas.index = frame.index;
+        }
+        // Anchor moves the sprite around its transform so the offset doesn't touch gameplay position
+        let anchor = Anchor::Custom(Vec2::new(-frame.offset.0, -frame.offset.1) / sheet.frame_size);
+        if sprite.flip_x != frame.flip_x || sprite.anchor != anchor {
+            sprite.flip_x = frame.flip_x;
+            sprite.anchor = anchor;
+        }
+    }
+}
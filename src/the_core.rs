@@ -1,8 +1,26 @@
 use bevy::prelude::*;
 
-use crate::{game, map::{self, TileBundle, TileState}, spriteanims};
+use crate::{effects::{EffectKind, SpawnEffectEvent}, lighting::Lit, loading::GameAssets, map::{self, MapSize}, sfx::{Sfx, SfxEvent}};
+#[cfg(feature = "audio")]
+use crate::music_player::GameIntensity;
 
 const CORE_ROTATE_PERIOD: f32 = 0.8;
+/// Health the core starts each run with
+pub const CORE_MAX_HEALTH: f32 = 100.;
+
+/// What's left of the core, the run is lost when it hits 0
+#[derive(Resource, Clone, Copy, PartialEq, Debug)]
+pub struct CoreHealth(pub f32);
+
+impl Default for CoreHealth {
+    fn default() -> Self {
+        CoreHealth(CORE_MAX_HEALTH)
+    }
+}
+
+/// Nothing takes damage while it's on. Only the dev console turns it on.
+#[derive(Resource, Default)]
+pub struct GodMode(pub bool);
 
 #[derive(Component)]
 pub struct Core;
@@ -29,24 +47,42 @@ impl CoreBundle {
             core_rotation: CoreRotation(Timer::from_seconds(CORE_ROTATE_PERIOD, TimerMode::Repeating)),
         }
     }
-} 
+}
+
+impl Default for CoreBundle {
+    fn default() -> Self {
+        CoreBundle::new()
+    }
+}
+
+/// Where the core sits, in homestead coordinates
+const CORE_TILE: (i32, i32) = (20, 2);
+
+/// The map tile the core sits on
+pub fn core_tile(map_size: &MapSize) -> (i32, i32) {
+    map_size.home(CORE_TILE.0, CORE_TILE.1)
+}
 
-pub fn setup (mut commands: Commands, assets: Res<AssetServer>) {
+pub fn setup (mut commands: Commands, game_assets: Res<GameAssets>, map_size: Res<MapSize>) {
+    commands.insert_resource(CoreHealth::default());
+    let (core_x, core_y) = core_tile(&map_size);
+    let (core_x, core_y) = map::get_world(core_x, core_y);
     // Spawn the core background
-    commands.spawn(
+    commands.spawn((
         SpriteBundle {
-            texture: assets.load("images/core_background.png"),
-            transform: Transform::from_xyz(20. * 32., 2. * 32., 2.),
+            texture: game_assets.core_background.clone(),
+            transform: Transform::from_xyz(core_x, core_y, 2.),
             ..default()
-        }
-    );
+        },
+        Lit,
+    ));
     // Spawn the core
     commands.spawn(
         (
             CoreBundle::new(),
             SpriteBundle {
-                texture: assets.load("images/core_orb.png"),
-                transform: Transform::from_xyz(20. * 32., 2. * 32., 2.),
+                texture: game_assets.core_orb.clone(),
+                transform: Transform::from_xyz(core_x, core_y, 2.),
                 ..default()
             }
         )
@@ -55,20 +91,22 @@ pub fn setup (mut commands: Commands, assets: Res<AssetServer>) {
         // Core Base Image (the orb)
         parent.spawn(
             (SpriteBundle {
-                texture: assets.load("images/core_orb.png"),
+                texture: game_assets.core_orb.clone(),
                 transform: Transform::from_xyz(0., 0., 2.),
                 ..default()
             },
-            Core
+            Core,
+            Lit,
         ));
         // Spawn the core spin object
         parent.spawn(
             (SpriteBundle {
-                texture: assets.load("images/core_spin.png"),
+                texture: game_assets.core_spin.clone(),
                 transform: Transform::from_xyz(0., 0., 3.),
                 ..default()
             },
-            CoreRotator, 
+            CoreRotator,
+            Lit,
         ));
         // Spawn the mosaic layer that indicates damage
         parent.spawn(
@@ -78,7 +116,7 @@ pub fn setup (mut commands: Commands, assets: Res<AssetServer>) {
                     color: Color::rgba(1.0, 1.0, 1.0, 0.0),
                     ..default()
                 },
-                texture: assets.load("images/core_death.png"),
+                texture: game_assets.core_death.clone(),
                 transform: Transform::from_xyz(0., 0., 4.),
                 ..default()
             }
@@ -86,6 +124,37 @@ pub fn setup (mut commands: Commands, assets: Res<AssetServer>) {
     });
 }
 
+/// Let the player know whenever the core loses health, with a bigger burst when it falls
+pub fn core_hurt(
+    core: Res<CoreHealth>,
+    map_size: Res<MapSize>,
+    mut last: Local<f32>,
+    mut ev_sfx: EventWriter<SfxEvent>,
+    mut ev_effects: EventWriter<SpawnEffectEvent>,
+) {
+    if !core.is_changed() {
+        return;
+    }
+    if core.0 < *last {
+        let (x, y) = core_tile(&map_size);
+        let (x, y) = map::get_world(x, y);
+        let effect = if core.0 <= 0. { EffectKind::CoreDestruction } else { EffectKind::CoreDamage };
+        ev_effects.send(SpawnEffectEvent { effect, position: Vec2::new(x, y) });
+        ev_sfx.send(SfxEvent(Sfx::CoreDamage));
+    }
+    *last = core.0;
+}
+
+/// The more the core's been hurt, the more intense the music gets, and once it's gone it's game over
+#[cfg(feature = "audio")]
+pub fn core_threat(core: Res<CoreHealth>, mut intensity: ResMut<GameIntensity>) {
+    if core.is_changed() {
+        intensity.threat = (1. - core.0 / CORE_MAX_HEALTH).clamp(0., 1.);
+        intensity.game_over = core.0 <= 0.;
+    }
+}
+
+#[allow(clippy::type_complexity)]
 pub fn core_update (
     mut core_query: Query<&mut CoreRotation, With<Core>>,
     mut param_set: ParamSet<(
@@ -125,7 +194,6 @@ pub fn core_update (
         for mut core_death_sprite in param_set.p1().iter_mut() {
             // This is not getting called
             let alpha = (progress * 360.).to_radians().cos().max(0.5);
-            println!("{}", alpha);
             // Get the current color
             core_death_sprite.color.set_a(alpha);
         }
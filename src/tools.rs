@@ -0,0 +1,182 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    day_night::{DayNightCycle, DayPhase},
+    game::Stockpile,
+    loading::GameAssets,
+    player::{self, PlayerAttack, PlayerStats},
+    sfx::{Sfx, SfxEvent},
+};
+
+/// Resources it takes to mend a worn hoe, whatever the tier
+pub const REPAIR_COST: u32 = 3;
+
+/// How good the player's hoe is, each tier has its own sprite in `GameAssets::hoes`
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum HoeTier {
+    #[default]
+    Hoe1,
+    Hoe2,
+    Hoe3,
+}
+
+impl HoeTier {
+    pub fn index(&self) -> usize {
+        match self {
+            HoeTier::Hoe1 => 0,
+            HoeTier::Hoe2 => 1,
+            HoeTier::Hoe3 => 2,
+        }
+    }
+
+    pub fn next(&self) -> Option<HoeTier> {
+        match self {
+            HoeTier::Hoe1 => Some(HoeTier::Hoe2),
+            HoeTier::Hoe2 => Some(HoeTier::Hoe3),
+            HoeTier::Hoe3 => None,
+        }
+    }
+
+    /// Resources it takes to get this tier from the one before
+    pub fn cost(&self) -> u32 {
+        match self {
+            HoeTier::Hoe1 => 0,
+            HoeTier::Hoe2 => 8,
+            HoeTier::Hoe3 => 15,
+        }
+    }
+
+    /// Tiles tilled in one go, across and up, centered on the one clicked
+    pub fn area(&self) -> (i32, i32) {
+        match self {
+            HoeTier::Hoe1 => (1, 1),
+            HoeTier::Hoe2 => (3, 1),
+            HoeTier::Hoe3 => (3, 3),
+        }
+    }
+
+    /// Scales how long a swing takes, tilling included. Lower swings more often.
+    pub fn cooldown(&self) -> f32 {
+        match self {
+            HoeTier::Hoe1 => 1.,
+            HoeTier::Hoe2 => 0.85,
+            HoeTier::Hoe3 => 0.7,
+        }
+    }
+
+    /// Health a swing takes off an enemy
+    pub fn damage(&self) -> f32 {
+        match self {
+            HoeTier::Hoe1 => 10.,
+            HoeTier::Hoe2 => 16.,
+            HoeTier::Hoe3 => 25.,
+        }
+    }
+
+    /// Uses a fresh hoe is good for
+    pub fn durability(&self) -> u32 {
+        match self {
+            HoeTier::Hoe1 => 40,
+            HoeTier::Hoe2 => 60,
+            HoeTier::Hoe3 => 80,
+        }
+    }
+}
+
+/// The player's tools for this run
+#[derive(Resource, Clone, Copy, PartialEq, Debug)]
+pub struct Tools {
+    pub hoe: HoeTier,
+    /// Uses left before the hoe needs mending
+    pub durability: u32,
+    /// Whether tools wear out at all
+    pub wear: bool,
+}
+
+impl Tools {
+    pub fn new(wear: bool) -> Tools {
+        Tools { hoe: HoeTier::Hoe1, durability: HoeTier::Hoe1.durability(), wear }
+    }
+
+    pub fn broken(&self) -> bool {
+        self.wear && self.durability == 0
+    }
+
+    /// Tiles the hoe works when used on a tile
+    pub fn hoe_tiles(&self, (x, y): (i32, i32)) -> impl Iterator<Item = (i32, i32)> {
+        let (width, height) = self.hoe.area();
+        (-(width / 2)..=width / 2).flat_map(move |dx| (-(height / 2)..=height / 2).map(move |dy| (x + dx, y + dy)))
+    }
+
+    /// Wear the hoe down by one use
+    pub fn wear_hoe(&mut self) {
+        if self.wear {
+            self.durability = self.durability.saturating_sub(1);
+        }
+    }
+}
+
+impl Default for Tools {
+    fn default() -> Self {
+        Tools::new(true)
+    }
+}
+
+/// Buying from the day shop
+#[derive(Event, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum ToolShopEvent {
+    UpgradeHoe,
+    RepairHoe,
+}
+
+/// The shop is only open while it's light out, and everything costs resources
+pub fn tool_shop(
+    mut ev_shop: EventReader<ToolShopEvent>,
+    cycle: Res<DayNightCycle>,
+    mut tools: ResMut<Tools>,
+    mut stockpile: ResMut<Stockpile>,
+    mut ev_sfx: EventWriter<SfxEvent>,
+) {
+    for ev in ev_shop.read() {
+        if !matches!(cycle.phase, DayPhase::Dawn | DayPhase::Day) {
+            continue;
+        }
+        let (cost, upgraded) = match ev {
+            ToolShopEvent::UpgradeHoe => {
+                let Some(next) = tools.hoe.next() else { continue; };
+                (next.cost(), Tools { hoe: next, durability: next.durability(), ..*tools })
+            }
+            ToolShopEvent::RepairHoe => {
+                if !tools.wear || tools.durability == tools.hoe.durability() {
+                    continue;
+                }
+                (REPAIR_COST, Tools { durability: tools.hoe.durability(), ..*tools })
+            }
+        };
+        if stockpile.0 < cost {
+            continue;
+        }
+        stockpile.0 -= cost;
+        *tools = upgraded;
+        ev_sfx.send(SfxEvent(Sfx::ToolUse));
+    }
+}
+
+/// Swap the hoe sprite and swing speed to match the tier
+pub fn update_hoe(
+    tools: Res<Tools>,
+    game_assets: Res<GameAssets>,
+    mut players: Query<(&PlayerStats, &mut PlayerAttack)>,
+    mut hoes: Query<&mut Handle<Image>, With<player::Hoe>>,
+) {
+    if !tools.is_changed() {
+        return;
+    }
+    for mut texture in hoes.iter_mut() {
+        *texture = game_assets.hoes[tools.hoe.index()].clone();
+    }
+    for (stats, mut attack) in players.iter_mut() {
+        attack.0.set_duration(std::time::Duration::from_secs_f32(stats.swing_cooldown * tools.hoe.cooldown()));
+    }
+}
@@ -0,0 +1,371 @@
+use bevy::prelude::*;
+
+use crate::{
+    building::{self, Building, Buildable, Buildings, Health},
+    crops::{Crop, Crops},
+    day_night::{DayNightCycle, DayPhase, PhaseChangedEvent},
+    game::Stockpile,
+    loading::{GameAssets, TileAtlas},
+    map::{MapSize, Tile, TileMap, TileState},
+    pathfinding,
+    player::ClickEvent,
+    the_core,
+    tools::Tools,
+    walls,
+};
+
+/// Most edits that can be taken back
+const UNDO_LIMIT: usize = 10;
+
+/// Take back the last tile edit
+#[derive(Event, Clone, Copy, Debug)]
+pub struct UndoEvent;
+
+/// Everything one tick's worth of clicks changed, enough to put it all back
+struct Edit {
+    /// Tiles as they were, oldest change first
+    tiles: Vec<((i32, i32), Tile)>,
+    /// Crops that were cleared
+    crops: Vec<((i32, i32), Crop)>,
+    /// Change in the stockpile
+    resources: i64,
+    /// Hoe uses spent
+    wear: u32,
+    placed: Vec<(i32, i32)>,
+    /// Buildings taken down, with the health they had
+    removed: Vec<((i32, i32), Buildable, f32)>,
+}
+
+/// What things looked like before this tick's clicks
+#[allow(clippy::type_complexity)]
+struct Snapshot {
+    crops: Vec<((i32, i32), Crop)>,
+    buildings: Vec<((i32, i32), Option<(Buildable, f32)>)>,
+    stockpile: u32,
+    durability: u32,
+}
+
+/// The day's edits, newest last. Only daylight work can be undone, and it's forgotten at dusk.
+#[derive(Resource, Default)]
+pub struct EditHistory {
+    edits: Vec<Edit>,
+    snapshot: Option<Snapshot>,
+}
+
+fn daylight(cycle: &DayNightCycle) -> bool {
+    matches!(cycle.phase, DayPhase::Dawn | DayPhase::Day)
+}
+
+/// Put the last edit back the way it was, as long as any harvest it paid out can be given back
+/// and any walls it took down can go back up without closing off the core
+#[allow(clippy::too_many_arguments)]
+pub fn undo_edits(
+    mut commands: Commands,
+    mut ev_undo: EventReader<UndoEvent>,
+    mut ev_phase: EventReader<PhaseChangedEvent>,
+    cycle: Res<DayNightCycle>,
+    mut history: ResMut<EditHistory>,
+    mut tile_map: ResMut<TileMap>,
+    mut crops: ResMut<Crops>,
+    mut stockpile: ResMut<Stockpile>,
+    mut tools: ResMut<Tools>,
+    mut buildings: ResMut<Buildings>,
+    placed: Query<&Building>,
+    map_size: Res<MapSize>,
+    game_assets: Res<GameAssets>,
+    atlas: Res<TileAtlas>,
+) {
+    if ev_phase.read().any(|ev| ev.to == DayPhase::Dusk) {
+        history.edits.clear();
+    }
+    for _ in ev_undo.read() {
+        if !daylight(&cycle) {
+            continue;
+        }
+        let Some(edit) = history.edits.pop() else { continue; };
+        let refunded = stockpile.0 as i64 - edit.resources;
+        if refunded < 0 || seals_core(&edit, &tile_map, &map_size, &buildings, &placed) {
+            history.edits.push(edit);
+            continue;
+        }
+        stockpile.0 = refunded as u32;
+        tools.durability = (tools.durability + edit.wear).min(tools.hoe.durability());
+
+        for (tile, old) in edit.tiles.iter().rev() {
+            tile_map.set(tile.0, tile.1, *old);
+        }
+        for (tile, crop) in edit.crops {
+            crops.0.insert(tile, crop);
+        }
+        for tile in edit.placed {
+            if let Some(entity) = buildings.0.remove(&tile) {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+        for (tile, kind, health) in edit.removed {
+            let entity = building::spawn_building(&mut commands, &game_assets, &atlas, kind, tile, health);
+            buildings.0.insert(tile, entity);
+        }
+    }
+}
+
+/// Whether putting back the walls an edit took down would leave no way in to the core, the same
+/// rule `building::can_place` holds new walls to. Walls the edit put up are coming down again.
+fn seals_core(edit: &Edit, tile_map: &TileMap, map_size: &MapSize, buildings: &Buildings, placed: &Query<&Building>) -> bool {
+    let core = the_core::core_tile(map_size);
+    let mut restored: Vec<(i32, i32)> = Vec::new();
+    for (tile, kind, _) in edit.removed.iter() {
+        if !matches!(kind, Buildable::Wall(_)) {
+            continue;
+        }
+        let walled = |next: (i32, i32)| {
+            restored.contains(&next) || (!edit.placed.contains(&next) && walls::is_wall(buildings, placed, next))
+        };
+        if pathfinding::seals_core(tile_map.width(), tile_map.height(), core, *tile, walled) {
+            return true;
+        }
+        restored.push(*tile);
+    }
+    false
+}
+
+/// Before the clicks land, note what they could change and start the tile journal
+#[allow(clippy::too_many_arguments)]
+pub fn begin_edit(
+    mut ev_click: EventReader<ClickEvent>,
+    cycle: Res<DayNightCycle>,
+    mut history: ResMut<EditHistory>,
+    mut tile_map: ResMut<TileMap>,
+    crops: Res<Crops>,
+    stockpile: Res<Stockpile>,
+    tools: Res<Tools>,
+    buildings: Res<Buildings>,
+    placed: Query<(&Building, &Health)>,
+) {
+    let clicked: Vec<(i32, i32)> = ev_click.read().map(|ev| ev.0).collect();
+    if clicked.is_empty() || !daylight(&cycle) {
+        return;
+    }
+    tile_map.start_journal();
+    history.snapshot = Some(Snapshot {
+        crops: clicked.iter().filter_map(|tile| Some((*tile, *crops.0.get(tile)?))).collect(),
+        buildings: clicked.iter().map(|tile| {
+            let building = buildings.0.get(tile).and_then(|entity| placed.get(*entity).ok());
+            (*tile, building.map(|(building, health)| (building.0, health.0)))
+        }).collect(),
+        stockpile: stockpile.0,
+        durability: tools.durability,
+    });
+}
+
+/// Once the clicks have landed, keep whatever they changed
+pub fn finish_edit(
+    mut history: ResMut<EditHistory>,
+    mut tile_map: ResMut<TileMap>,
+    crops: Res<Crops>,
+    stockpile: Res<Stockpile>,
+    tools: Res<Tools>,
+    buildings: Res<Buildings>,
+) {
+    let Some(snapshot) = history.snapshot.take() else { return; };
+    let tiles = tile_map.take_journal();
+    let edit = Edit {
+        tiles,
+        // A raked crop hangs around in `Crops` until it next grows, so go by the tile too
+        crops: snapshot.crops.into_iter()
+            .filter(|(tile, _)| {
+                let planted = tile_map.get(tile.0, tile.1).is_some_and(|tile| tile.state == TileState::Planted);
+                !planted || !crops.0.contains_key(tile)
+            })
+            .collect(),
+        resources: stockpile.0 as i64 - snapshot.stockpile as i64,
+        wear: snapshot.durability.saturating_sub(tools.durability),
+        placed: snapshot.buildings.iter()
+            .filter(|(tile, before)| before.is_none() && buildings.occupied(tile.0, tile.1))
+            .map(|(tile, _)| *tile)
+            .collect(),
+        removed: snapshot.buildings.iter()
+            .filter_map(|(tile, before)| {
+                let (kind, health) = (*before)?;
+                (!buildings.occupied(tile.0, tile.1)).then_some((*tile, kind, health))
+            })
+            .collect(),
+    };
+    if edit.tiles.is_empty() && edit.crops.is_empty() && edit.placed.is_empty() && edit.removed.is_empty() {
+        return;
+    }
+    history.edits.push(edit);
+    if history.edits.len() > UNDO_LIMIT {
+        history.edits.remove(0);
+    }
+}
+
+/// Ctrl+Z takes back the last edit
+pub fn undo_keys(keycode: Res<ButtonInput<KeyCode>>, mut ev_undo: EventWriter<UndoEvent>) {
+    let ctrl = keycode.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    if ctrl && keycode.just_pressed(KeyCode::KeyZ) {
+        ev_undo.send(UndoEvent);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{day_night::{DayCount, DayNightSchedule}, player::PlayerTool, sim, walls::WallTier};
+
+    /// Open ground to work on, the first `count` untoiled tiles on the map
+    fn untoiled(app: &App, count: usize) -> Vec<(i32, i32)> {
+        let tile_map = app.world.resource::<TileMap>();
+        (0..tile_map.width())
+            .flat_map(|x| (0..tile_map.height()).map(move |y| (x, y)))
+            .filter(|(x, y)| tile_map.get(*x, *y).is_some_and(|tile| tile.state == TileState::Untoiled))
+            .take(count)
+            .collect()
+    }
+
+    fn click(app: &mut App, tile: (i32, i32), tool: PlayerTool) {
+        app.world.send_event(ClickEvent(tile, tool));
+        app.update();
+    }
+
+    fn undo(app: &mut App) {
+        app.world.send_event(UndoEvent);
+        app.update();
+    }
+
+    fn building_count(app: &mut App) -> usize {
+        app.world.query::<&Building>().iter(&app.world).count()
+    }
+
+    #[test]
+    fn undo_takes_back_tilling() {
+        let mut app = sim::fresh_run();
+        let tile = untoiled(&app, 1)[0];
+        let before = *app.world.resource::<TileMap>().get(tile.0, tile.1).unwrap();
+        let durability = app.world.resource::<Tools>().durability;
+
+        app.world.send_event(ClickEvent(tile, PlayerTool::Tiller));
+        app.update();
+        assert_eq!(app.world.resource::<TileMap>().get(tile.0, tile.1).unwrap().state, TileState::Toiled);
+        assert_eq!(app.world.resource::<Tools>().durability, durability - 1);
+
+        app.world.send_event(UndoEvent);
+        app.update();
+        assert_eq!(*app.world.resource::<TileMap>().get(tile.0, tile.1).unwrap(), before);
+        assert_eq!(app.world.resource::<Tools>().durability, durability);
+    }
+
+    #[test]
+    fn undo_takes_down_a_building_and_refunds_it() {
+        let mut app = sim::fresh_run();
+        let tile = untoiled(&app, 1)[0];
+        let resources = app.world.resource::<Stockpile>().0;
+
+        click(&mut app, tile, PlayerTool::Build(Buildable::Torch));
+        assert!(app.world.resource::<Buildings>().occupied(tile.0, tile.1));
+        assert_eq!(app.world.resource::<Stockpile>().0, resources - Buildable::Torch.cost());
+
+        undo(&mut app);
+        assert!(!app.world.resource::<Buildings>().occupied(tile.0, tile.1));
+        assert_eq!(building_count(&mut app), 0);
+        assert_eq!(app.world.resource::<Stockpile>().0, resources);
+    }
+
+    #[test]
+    fn undo_puts_back_a_raked_building_as_it_was() {
+        let mut app = sim::fresh_run();
+        let tile = untoiled(&app, 1)[0];
+        click(&mut app, tile, PlayerTool::Build(Buildable::Torch));
+        let entity = app.world.resource::<Buildings>().0[&tile];
+        app.world.get_mut::<Health>(entity).unwrap().0 = 4.;
+        let resources = app.world.resource::<Stockpile>().0;
+
+        click(&mut app, tile, PlayerTool::Rake);
+        assert!(!app.world.resource::<Buildings>().occupied(tile.0, tile.1));
+        assert_eq!(app.world.resource::<Stockpile>().0, resources + Buildable::Torch.cost() / 2);
+
+        undo(&mut app);
+        let entity = app.world.resource::<Buildings>().0[&tile];
+        assert_eq!(app.world.get::<Building>(entity).unwrap().0, Buildable::Torch);
+        assert_eq!(app.world.get::<Health>(entity).unwrap().0, 4.);
+        assert_eq!(app.world.resource::<Stockpile>().0, resources);
+    }
+
+    #[test]
+    fn undo_waits_until_the_payout_can_be_given_back() {
+        let mut app = sim::fresh_run();
+        let tile = untoiled(&app, 1)[0];
+        click(&mut app, tile, PlayerTool::Build(Buildable::Torch));
+        click(&mut app, tile, PlayerTool::Rake);
+        // Raking paid out, and it's been spent since
+        app.world.resource_mut::<Stockpile>().0 = 0;
+
+        undo(&mut app);
+        assert!(!app.world.resource::<Buildings>().occupied(tile.0, tile.1));
+        assert_eq!(app.world.resource::<EditHistory>().edits.len(), 2);
+
+        app.world.resource_mut::<Stockpile>().0 = 1;
+        undo(&mut app);
+        assert!(app.world.resource::<Buildings>().occupied(tile.0, tile.1));
+        assert_eq!(app.world.resource::<Stockpile>().0, 0);
+    }
+
+    #[test]
+    fn dusk_forgets_the_days_edits() {
+        let mut app = sim::fresh_run();
+        let tile = untoiled(&app, 1)[0];
+        click(&mut app, tile, PlayerTool::Tiller);
+        assert_eq!(app.world.resource::<EditHistory>().edits.len(), 1);
+
+        app.world.resource_scope(|world, mut cycle: Mut<DayNightCycle>| {
+            let schedule = *world.resource::<DayNightSchedule>();
+            let mut day = *world.resource::<DayCount>();
+            let events = cycle.skip_to(&schedule, &mut day, DayPhase::Dusk);
+            world.send_event_batch(events);
+        });
+        app.update();
+        assert!(app.world.resource::<EditHistory>().edits.is_empty());
+    }
+
+    #[test]
+    fn undo_wont_put_back_a_wall_that_seals_the_core() {
+        let mut app = sim::fresh_run();
+        app.world.resource_mut::<Stockpile>().0 = 1000;
+        let wall = PlayerTool::Build(Buildable::Wall(WallTier::Wood));
+        // Around the homestead from the bottom edge and back, all but one corner of it
+        let mut ring: Vec<(i32, i32)> = (0..=18).map(|y| (18, y)).collect();
+        ring.extend((19..52).map(|x| (x, 18)));
+        ring.extend((0..=18).rev().map(|y| (52, y)));
+        let last = ring.pop().unwrap();
+        for tile in ring {
+            app.world.send_event(ClickEvent(tile, wall));
+        }
+        app.update();
+        let gap = (18, 10);
+        assert!(app.world.resource::<Buildings>().occupied(gap.0, gap.1));
+        click(&mut app, gap, PlayerTool::Rake);
+
+        // Close the ring behind the history's back, so the raked wall is the one that would seal it
+        let entity = app.world.spawn(Building(Buildable::Wall(WallTier::Wood))).id();
+        app.world.resource_mut::<Buildings>().0.insert(last, entity);
+        let resources = app.world.resource::<Stockpile>().0;
+
+        undo(&mut app);
+        assert!(!app.world.resource::<Buildings>().occupied(gap.0, gap.1));
+        assert_eq!(app.world.resource::<Stockpile>().0, resources);
+        assert_eq!(app.world.resource::<EditHistory>().edits.len(), 2);
+
+        app.world.resource_mut::<Buildings>().0.remove(&last);
+        undo(&mut app);
+        assert!(app.world.resource::<Buildings>().occupied(gap.0, gap.1));
+    }
+
+    #[test]
+    fn history_keeps_the_last_few_edits() {
+        let mut app = sim::fresh_run();
+        for tile in untoiled(&app, UNDO_LIMIT + 2) {
+            click(&mut app, tile, PlayerTool::Tiller);
+        }
+        assert_eq!(app.world.resource::<EditHistory>().edits.len(), UNDO_LIMIT);
+    }
+}
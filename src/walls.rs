@@ -0,0 +1,105 @@
+//! Walls the player builds to block lanes. They join up with their neighbors like the base walls do,
+//! and nothing walks through them.
+
+use bevy::{prelude::*, utils::HashSet};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    building::{Buildable, Building, Buildings},
+    loading::{GameAssets, TileAtlas},
+};
+
+/// What a wall is made of, tougher ones cost more
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+pub enum WallTier {
+    #[default]
+    Wood,
+    Concrete,
+    Reinforced,
+}
+
+impl WallTier {
+    /// The tier after this one, going back round to wood, for the wall button
+    pub fn next(&self) -> WallTier {
+        match self {
+            WallTier::Wood => WallTier::Concrete,
+            WallTier::Concrete => WallTier::Reinforced,
+            WallTier::Reinforced => WallTier::Wood,
+        }
+    }
+
+    pub fn cost(&self) -> u32 {
+        match self {
+            WallTier::Wood => 1,
+            WallTier::Concrete => 3,
+            WallTier::Reinforced => 6,
+        }
+    }
+
+    pub fn max_health(&self) -> f32 {
+        match self {
+            WallTier::Wood => 30.,
+            WallTier::Concrete => 80.,
+            WallTier::Reinforced => 200.,
+        }
+    }
+
+    /// Tint over the base wall sprite, there's no art of their own yet
+    pub fn color(&self) -> Color {
+        match self {
+            WallTier::Wood => Color::rgb(0.75, 0.55, 0.35),
+            WallTier::Concrete => Color::rgb(0.8, 0.8, 0.8),
+            WallTier::Reinforced => Color::rgb(0.6, 0.7, 0.9),
+        }
+    }
+}
+
+/// Whether there's a wall on a tile
+pub fn is_wall(buildings: &Buildings, placed: &Query<&Building>, tile: (i32, i32)) -> bool {
+    buildings.0.get(&tile)
+        .and_then(|entity| placed.get(*entity).ok())
+        .is_some_and(|building| matches!(building.0, Buildable::Wall(_)))
+}
+
+/// Which sprite a wall uses and how far it's turned, from the walls next to it.
+/// Straight walls run across, or up and down with a turn of 90. A corner with no turn
+/// joins the walls left of and below it, like the base's top right corner.
+fn wall_sprite(north: bool, east: bool, south: bool, west: bool) -> (bool, f32) {
+    match (north, east, south, west) {
+        (false, false, true, true) => (true, 0.),
+        (false, true, true, false) => (true, 90.),
+        (true, true, false, false) => (true, 180.),
+        (true, false, false, true) => (true, -90.),
+        _ if (north || south) && !(east || west) => (false, 90.),
+        _ => (false, 0.),
+    }
+}
+
+/// Re-pick every wall's sprite whenever a building goes up or comes down
+pub fn connect_walls(
+    buildings: Res<Buildings>,
+    game_assets: Res<GameAssets>,
+    atlas: Res<TileAtlas>,
+    mut walls: Query<(&Building, &mut TextureAtlas, &mut Transform)>,
+) {
+    if !buildings.is_changed() {
+        return;
+    }
+    let walled: HashSet<(i32, i32)> = buildings.0.iter()
+        .filter(|(_, entity)| walls.get(**entity).is_ok_and(|(building, _, _)| matches!(building.0, Buildable::Wall(_))))
+        .map(|(tile, _)| *tile)
+        .collect();
+
+    for tile in walled.iter() {
+        let Ok((_, mut sprite, mut transform)) = walls.get_mut(buildings.0[tile]) else { continue; };
+        let (x, y) = *tile;
+        let (corner, angle) = wall_sprite(
+            walled.contains(&(x, y + 1)),
+            walled.contains(&(x + 1, y)),
+            walled.contains(&(x, y - 1)),
+            walled.contains(&(x - 1, y)),
+        );
+        *sprite = atlas.sprite(if corner { &game_assets.base_corner } else { &game_assets.base_wall });
+        transform.rotation = Quat::from_rotation_z(angle.to_radians());
+    }
+}
@@ -0,0 +1,320 @@
+use bevy::prelude::*;
+use bevy_hanabi::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Serialize;
+
+use crate::{
+    bases::{Bases, BASE_MAX_HEALTH},
+    building::{Building, Buildings, Health},
+    camera::MainCamera,
+    day_night::{DayPhase, PhaseChangedEvent},
+    loading::GameAssets,
+    run::Run,
+    the_core::GodMode,
+};
+
+use super::{RESOLUTION_X, RESOLUTION_Y};
+
+/// Share of its full health a building loses per second while a solar flare is overhead,
+/// so torches and walls alike see out about three days of flares from new
+const FLARE_WEAR: f32 = 1. / 180.;
+/// Share of flare damage that gets through a base's roof
+const BASE_SHELTER: f32 = 0.5;
+
+/// What the sky is doing today
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize)]
+pub enum WeatherKind {
+    #[default]
+    Clear,
+    /// Dark, hard to see, and slow to walk through
+    DustStorm,
+    /// Crops love it, buildings don't
+    SolarFlare,
+    /// Crops without a light to keep them warm wilt
+    ColdSnap,
+}
+
+impl WeatherKind {
+    /// Multiplier on the sun's brightness
+    pub fn sun_factor(&self) -> f32 {
+        match self {
+            WeatherKind::Clear => 1.,
+            WeatherKind::DustStorm => 0.6,
+            WeatherKind::SolarFlare => 1.15,
+            WeatherKind::ColdSnap => 0.9,
+        }
+    }
+
+    /// Multiplier on how fast the player walks
+    pub fn move_factor(&self) -> f32 {
+        match self {
+            WeatherKind::DustStorm => 0.7,
+            _ => 1.,
+        }
+    }
+
+    /// Multiplier on how fast crops grow
+    pub fn growth_factor(&self) -> f32 {
+        match self {
+            WeatherKind::SolarFlare => 1.5,
+            WeatherKind::ColdSnap => 0.,
+            _ => 1.,
+        }
+    }
+
+    /// Music playback rate, storms drag the tempo down
+    pub fn music_rate(&self) -> f64 {
+        match self {
+            WeatherKind::Clear => 1.,
+            WeatherKind::DustStorm => 0.85,
+            WeatherKind::SolarFlare => 1.1,
+            WeatherKind::ColdSnap => 0.9,
+        }
+    }
+
+    /// HUD warning while the weather is active
+    pub fn warning(&self) -> &'static str {
+        match self {
+            WeatherKind::Clear => "",
+            WeatherKind::DustStorm => "Dust storm! Low light and slow going",
+            WeatherKind::SolarFlare => "Solar flare! Crops grow fast, buildings burn",
+            WeatherKind::ColdSnap => "Cold snap! Keep crops lit or they wilt",
+        }
+    }
+
+    /// Screen tint over the whole view
+    fn tint(&self) -> Color {
+        match self {
+            WeatherKind::Clear => Color::NONE,
+            WeatherKind::DustStorm => Color::rgba(0.55, 0.3, 0.15, 0.3),
+            WeatherKind::SolarFlare => Color::rgba(1., 0.85, 0.4, 0.12),
+            WeatherKind::ColdSnap => Color::rgba(0.6, 0.75, 1., 0.15),
+        }
+    }
+}
+
+/// Weather for the current day
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Weather {
+    pub kind: WeatherKind,
+}
+
+/// Roll the weather for a day. The same seed and day always give the same weather.
+pub fn roll_weather(seed: u64, day: u32) -> WeatherKind {
+    // Give the player one calm day to get set up
+    if day <= 1 {
+        return WeatherKind::Clear;
+    }
+    let mut rng = StdRng::seed_from_u64(seed ^ (day as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    match rng.gen_range(0..100) {
+        0..=54 => WeatherKind::Clear,
+        55..=74 => WeatherKind::DustStorm,
+        75..=89 => WeatherKind::SolarFlare,
+        _ => WeatherKind::ColdSnap,
+    }
+}
+
+/// Particle effects for each kind of weather
+#[derive(Resource)]
+pub struct WeatherEffects {
+    dust: Handle<EffectAsset>,
+    flare: Handle<EffectAsset>,
+    snow: Handle<EffectAsset>,
+}
+
+impl WeatherEffects {
+    fn get(&self, kind: WeatherKind) -> Option<Handle<EffectAsset>> {
+        match kind {
+            WeatherKind::Clear => None,
+            WeatherKind::DustStorm => Some(self.dust.clone()),
+            WeatherKind::SolarFlare => Some(self.flare.clone()),
+            WeatherKind::ColdSnap => Some(self.snow.clone()),
+        }
+    }
+}
+
+/// Particles and tint drawn for the current weather
+#[derive(Component)]
+pub struct WeatherOverlay;
+
+/// Particle emitter that sits over the camera
+#[derive(Component)]
+pub struct FollowCamera;
+
+/// HUD line warning about the weather
+#[derive(Component)]
+pub struct WeatherBanner;
+
+/// Screen filling emitter for weather particles, in the emitter's local space
+fn overlay_effect(name: &str, rate: f32, lifetime: f32, velocity: Vec3, spread: Vec3, color: Vec4, size: f32) -> EffectAsset {
+    let writer = ExprWriter::new();
+    // Cover a bit more than the screen so zooming out doesn't show the edges
+    let area = Vec3::new(RESOLUTION_X * 1.6, RESOLUTION_Y * 1.6, 0.);
+
+    let init_pos = SetAttributeModifier::new(
+        Attribute::POSITION,
+        ((writer.rand(VectorType::VEC3F) - writer.lit(Vec3::splat(0.5))) * writer.lit(area)).expr(),
+    );
+    let init_vel = SetAttributeModifier::new(
+        Attribute::VELOCITY,
+        (writer.lit(velocity) + (writer.rand(VectorType::VEC3F) - writer.lit(Vec3::splat(0.5))) * writer.lit(spread)).expr(),
+    );
+    let init_age = SetAttributeModifier::new(Attribute::AGE, writer.lit(0.).expr());
+    let init_lifetime = SetAttributeModifier::new(Attribute::LIFETIME, writer.lit(lifetime).expr());
+
+    // Fade in and out so particles don't pop
+    let mut gradient = Gradient::new();
+    gradient.add_key(0.0, color.truncate().extend(0.));
+    gradient.add_key(0.2, color);
+    gradient.add_key(0.8, color);
+    gradient.add_key(1.0, color.truncate().extend(0.));
+
+    EffectAsset::new(4096, Spawner::rate(rate.into()), writer.finish())
+        .with_name(name)
+        .with_simulation_space(SimulationSpace::Local)
+        .init(init_pos)
+        .init(init_vel)
+        .init(init_age)
+        .init(init_lifetime)
+        .render(SizeOverLifetimeModifier {
+            gradient: Gradient::constant(Vec2::splat(size)),
+            screen_space_size: false,
+        })
+        .render(ColorOverLifetimeModifier { gradient })
+}
+
+pub fn setup_effects(mut commands: Commands, effects: Option<ResMut<Assets<EffectAsset>>>) {
+    // No particles without a renderer, the tint and banner still work
+    let Some(mut effects) = effects else { return; };
+    commands.insert_resource(WeatherEffects {
+        dust: effects.add(overlay_effect("dust_storm", 400., 3., Vec3::new(260., -20., 0.), Vec3::new(120., 60., 0.), Vec4::new(0.7, 0.45, 0.25, 0.8), 4.)),
+        flare: effects.add(overlay_effect("solar_flare", 80., 1.5, Vec3::new(0., 30., 0.), Vec3::new(40., 40., 0.), Vec4::new(2., 1.6, 0.6, 0.9), 3.)),
+        snow: effects.add(overlay_effect("cold_snap", 250., 4., Vec3::new(-20., -80., 0.), Vec3::new(30., 20., 0.), Vec4::new(0.9, 0.95, 1., 0.9), 3.)),
+    });
+}
+
+/// Put up the HUD banner
+pub fn setup(mut commands: Commands, game_assets: Res<GameAssets>) {
+    commands.spawn(NodeBundle {
+        style: Style {
+            width: Val::Percent(100.0),
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        ..default()
+    }).with_children(|parent| {
+        parent.spawn((WeatherBanner, TextBundle::from_section(
+            "",
+            TextStyle {
+                font: game_assets.ui_font.clone(),
+                font_size: 28.0,
+                color: Color::rgb(1.0, 0.8, 0.4),
+            },
+        )));
+    });
+}
+
+/// Each new day brings new weather
+pub fn roll_daily_weather(
+    mut ev_phase: EventReader<PhaseChangedEvent>,
+    run: Res<Run>,
+    mut weather: ResMut<Weather>,
+) {
+    for ev in ev_phase.read() {
+        if ev.to == DayPhase::Dawn {
+            let kind = roll_weather(run.seed, ev.day);
+            if weather.kind != kind {
+                weather.kind = kind;
+            }
+        }
+    }
+}
+
+/// Swap the overlays and banner when the weather changes
+pub fn show_weather(
+    mut commands: Commands,
+    weather: Res<Weather>,
+    effects: Option<Res<WeatherEffects>>,
+    overlays: Query<Entity, With<WeatherOverlay>>,
+    mut banner: Query<&mut Text, With<WeatherBanner>>,
+) {
+    if !weather.is_changed() {
+        return;
+    }
+
+    for entity in overlays.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    if let Some(effect) = effects.and_then(|effects| effects.get(weather.kind)) {
+        commands.spawn((
+            ParticleEffectBundle {
+                effect: ParticleEffect::new(effect).with_z_layer_2d(Some(50.)),
+                ..default()
+            },
+            FollowCamera,
+            WeatherOverlay,
+        ));
+    }
+    if weather.kind != WeatherKind::Clear {
+        // Tint sits under the HUD and lets clicks through to the world
+        commands.spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    position_type: PositionType::Absolute,
+                    ..default()
+                },
+                background_color: weather.kind.tint().into(),
+                z_index: ZIndex::Global(-1),
+                ..default()
+            },
+            WeatherOverlay,
+        ));
+    }
+
+    for mut text in banner.iter_mut() {
+        text.sections[0].value = weather.kind.warning().to_string();
+    }
+}
+
+/// Keep weather particles centered on the view
+pub fn follow_camera(
+    camera: Query<&Transform, (With<MainCamera>, Without<FollowCamera>)>,
+    mut followers: Query<&mut Transform, With<FollowCamera>>,
+) {
+    let Ok(camera) = camera.get_single() else { return; };
+    for mut transform in followers.iter_mut() {
+        transform.translation.x = camera.translation.x;
+        transform.translation.y = camera.translation.y;
+    }
+}
+
+/// Solar flares scorch every building out in the open, and wear the bases down slower
+pub fn flare_damage(
+    mut commands: Commands,
+    time: Res<Time>,
+    weather: Res<Weather>,
+    god: Res<GodMode>,
+    mut buildings: ResMut<Buildings>,
+    mut bases: ResMut<Bases>,
+    mut query: Query<(Entity, &Building, &mut Health)>,
+) {
+    if weather.kind != WeatherKind::SolarFlare || god.0 {
+        return;
+    }
+    // Fallen bases stay where they are, ready to be mended
+    for base in bases.0.iter_mut().filter(|base| base.intact()) {
+        base.health = (base.health - BASE_MAX_HEALTH * FLARE_WEAR * BASE_SHELTER * time.delta_seconds()).max(0.);
+    }
+    for (entity, building, mut health) in query.iter_mut() {
+        health.0 -= building.0.max_health() * FLARE_WEAR * time.delta_seconds();
+        if health.0 <= 0. {
+            buildings.0.retain(|_, placed| *placed != entity);
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
@@ -0,0 +1,33 @@
+//! Whole runs played by the bot, through the library like `SolFarmer simulate` does.
+
+use solfarmer::{
+    run::CharacterProfile,
+    sim::{simulate, SimSettings, SimSummary},
+    the_core::CORE_MAX_HEALTH,
+    weather::WeatherKind,
+};
+
+fn run(seed: u64, days: u32) -> SimSummary {
+    simulate(&SimSettings { seed, days, profile: CharacterProfile::default() })
+}
+
+#[test]
+fn survives_the_days_asked_for() {
+    let summary = run(7, 2);
+    assert_eq!(summary.days_survived, 2);
+    // Enemies get at the core some nights, but the bot's torches keep most of them back
+    assert!(summary.core_health.iter().all(|health| *health > 0. && *health <= CORE_MAX_HEALTH));
+    assert_eq!(summary.resources.len(), 2);
+    // Day one is always calm
+    assert_eq!(summary.weather[0], WeatherKind::Clear);
+}
+
+#[test]
+fn same_seed_plays_out_the_same() {
+    assert_eq!(run(42, 2), run(42, 2));
+}
+
+#[test]
+fn bot_brings_in_a_harvest() {
+    assert!(run(3, 3).harvests > 0);
+}